@@ -0,0 +1,103 @@
+//! `bulk`: send `svc` commands to many services at once.
+//!
+//! `bulk [-j JOBS] [-w SECONDS] -OPTIONS SERVICE...` takes the `svc` option
+//! letters, controls up to `JOBS` services concurrently and prints a table
+//! of what happened to each. With `-w` it also waits for each service to
+//! reach the state the commands ask for. Exits 0 when every service was
+//! sent to, 1 when any was not, and 100 on a usage error.
+
+use std::time::Duration;
+
+use rupervise::control::{self, BulkOptions, Command, SendResult};
+use rupervise::wait::Condition;
+
+use super::svc;
+
+const USAGE: &str = "usage: bulk [-j jobs] [-w sec] -svc-options service ...";
+
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 100;
+
+fn parse_args(args: &[String]) -> Result<(BulkOptions, Vec<Command>, &[String]), ()> {
+    let mut opts = BulkOptions::default();
+    let mut wait = 0;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-j" => opts.jobs = args.get(i + 1).and_then(|s| s.parse().ok()).ok_or(())?,
+            "-w" => wait = args.get(i + 1).and_then(|s| s.parse().ok()).ok_or(())?,
+            _ => break,
+        }
+        i += 2;
+    }
+    let (commands, services) = svc::parse_args(&args[i..])?;
+    if commands.is_empty() || services.is_empty() || opts.jobs == 0 {
+        return Err(());
+    }
+    if wait > 0 {
+        opts.wait = condition_for(&commands).map(|c| (c, Duration::from_secs(wait)));
+    }
+    Ok((opts, commands, services))
+}
+
+/// The state `commands` should leave a service in, if there is one to wait
+/// for.
+fn condition_for(commands: &[Command]) -> Option<Condition> {
+    if commands.contains(&Command::Down) {
+        Some(Condition::Down)
+    } else if commands.contains(&Command::Term) || commands.contains(&Command::Kill) {
+        Some(Condition::PidChanged)
+    } else if commands.contains(&Command::Up) || commands.contains(&Command::Once) {
+        Some(Condition::Up)
+    } else {
+        None
+    }
+}
+
+pub fn main(args: &[String]) -> i32 {
+    let (opts, commands, services) = match parse_args(args) {
+        Ok(parsed) => parsed,
+        Err(()) => {
+            eprintln!("{}", USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    let results = control::send_all(services, &commands, &opts);
+
+    let width = results.iter().map(|(p, _)| p.display().to_string().len()).max().unwrap_or(0);
+    let mut failed = 0;
+    for (path, result) in &results {
+        println!("{:width$}  {}", path.display().to_string(), result, width = width);
+        if *result != SendResult::Sent {
+            failed += 1;
+        }
+    }
+    println!("{} of {} services sent, {} not running, {} permission denied, {} timed out",
+             results.len() - failed,
+             results.len(),
+             count(&results, &SendResult::NotRunning),
+             count(&results, &SendResult::PermissionDenied),
+             count(&results, &SendResult::TimedOut));
+
+    if failed > 0 { EXIT_FAILURE } else { 0 }
+}
+
+fn count<T>(results: &[(T, SendResult)], which: &SendResult) -> usize {
+    results.iter().filter(|&(_, r)| r == which).count()
+}
+
+#[cfg(test)]
+use super::strings;
+
+#[test]
+fn parse_options_then_svc_letters() {
+    let args = strings(&["-j", "4", "-w", "10", "-tu", "/service/a", "/service/b"]);
+    let (opts, commands, services) = parse_args(&args).unwrap();
+    assert_eq!(4, opts.jobs);
+    assert_eq!(Some((Condition::PidChanged, Duration::from_secs(10))), opts.wait);
+    assert_eq!(vec![Command::Term, Command::Up], commands);
+    assert_eq!(2, services.len());
+
+    assert!(parse_args(&strings(&["-j", "0", "-u", "/service/a"])).is_err());
+    assert!(parse_args(&strings(&["/service/a"])).is_err());
+}
@@ -0,0 +1,12 @@
+//! Command-line front ends that mimic the daemontools and runit tools.
+
+pub mod bulk;
+pub mod reconcile;
+pub mod silence;
+pub mod sv;
+pub mod svc;
+
+#[cfg(test)]
+fn strings(args: &[&str]) -> Vec<String> {
+    args.iter().map(|s| s.to_string()).collect()
+}
@@ -0,0 +1,71 @@
+//! `reconcile`: bring services to the state listed in a file.
+//!
+//! `reconcile [-n] FILE` prints, for each service in `FILE`, its state and
+//! the `svc` commands that converge it, then sends them. With `-n` only the
+//! plan is printed. Exits 0 when every service was or could be converged,
+//! 1 when any could not, 111 when `FILE` cannot be read and 100 on a usage
+//! error.
+
+use rupervise::reconcile::{self, DesiredState};
+
+const USAGE: &str = "usage: reconcile [-n] file";
+
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 100;
+pub const EXIT_BAD_FILE: i32 = 111;
+
+fn parse_args(args: &[String]) -> Result<(bool, &str), ()> {
+    match args {
+        [flag, file] if flag == "-n" => Ok((true, file)),
+        [file] if !file.starts_with('-') => Ok((false, file)),
+        _ => Err(()),
+    }
+}
+
+pub fn main(args: &[String]) -> i32 {
+    let (dry_run, file) = match parse_args(args) {
+        Ok(parsed) => parsed,
+        Err(()) => {
+            eprintln!("{}", USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    let desired = match DesiredState::load(file) {
+        Ok(desired) => desired,
+        Err(e) => {
+            eprintln!("reconcile: fatal: {}: {}", file, e);
+            return EXIT_BAD_FILE;
+        }
+    };
+
+    let steps = reconcile::plan(&desired);
+    for step in &steps {
+        println!("{}", step);
+    }
+    let mut rc = 0;
+    if dry_run {
+        if steps.iter().any(|s| s.commands.is_err()) {
+            rc = EXIT_FAILURE;
+        }
+        return rc;
+    }
+
+    for (step, result) in reconcile::apply(&steps) {
+        if let Err(e) = result {
+            eprintln!("reconcile: warning: {}: {}", step.service.name.display(), e);
+            rc = EXIT_FAILURE;
+        }
+    }
+    rc
+}
+
+#[cfg(test)]
+use super::strings;
+
+#[test]
+fn parse_dry_run() {
+    assert_eq!(Ok((true, "state")), parse_args(&strings(&["-n", "state"])));
+    assert_eq!(Ok((false, "state")), parse_args(&strings(&["state"])));
+    assert!(parse_args(&strings(&["-x", "state"])).is_err());
+    assert!(parse_args(&strings(&[])).is_err());
+}
@@ -0,0 +1,160 @@
+//! `silence`: keep hooks and webhooks quiet during planned work.
+//!
+//! `silence [-f FILE] [-d DURATION | -u TIME] [-b WHO] PATTERN [REASON...]`
+//! silences every service matching the glob `PATTERN` for `DURATION`
+//! (seconds, or with an `m`, `h` or `d` suffix; an hour by default) or until
+//! `TIME`. `silence [-f FILE]` lists the silences in force and
+//! `silence [-f FILE] -x PATTERN` lifts one. Exits 0 on success, 1 when
+//! there was nothing to lift, 111 when `FILE` cannot be read or written and
+//! 100 on a usage error.
+
+use std::env;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use rupervise::silence::{self, Silence, Silences};
+
+const USAGE: &str = "usage: silence [-f file] [-d duration | -u time] [-b who] pattern \
+                     [reason ...]\n       silence [-f file] [-x pattern]";
+
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 100;
+pub const EXIT_BAD_FILE: i32 = 111;
+
+const DEFAULT_DURATION: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    List,
+    Add(Silence),
+    Remove(String),
+}
+
+fn parse_args(args: &[String], now: SystemTime, who: &str) -> Result<(PathBuf, Action), ()> {
+    let mut file = silence::default_path();
+    let mut until = None;
+    let mut by = who.to_string();
+    let mut i = 0;
+    while i < args.len() && args[i].starts_with('-') {
+        let value = args.get(i + 1).ok_or(())?;
+        match args[i].as_str() {
+            "-f" => file = PathBuf::from(value),
+            "-d" if until.is_none() => until = Some(now + parse_duration(value).ok_or(())?),
+            "-u" if until.is_none() => until = Some(silence::parse_time(value).ok_or(())?),
+            "-b" => by = value.to_string(),
+            "-x" if i + 2 == args.len() => return Ok((file, Action::Remove(value.to_string()))),
+            _ => return Err(()),
+        }
+        i += 2;
+    }
+    let pattern = match args.get(i) {
+        Some(pattern) => pattern,
+        None if until.is_none() => return Ok((file, Action::List)),
+        None => return Err(()),
+    };
+    let silence = Silence {
+        pattern: pattern.to_string(),
+        until: until.unwrap_or(now + DEFAULT_DURATION),
+        by,
+        reason: args[i + 1..].join(" "),
+    };
+    if !silence.is_valid() {
+        return Err(());
+    }
+    Ok((file, Action::Add(silence)))
+}
+
+/// Parses `90`, `90s`, `30m`, `2h` or `1d`.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let (number, unit) = match s.char_indices().last()? {
+        (at, c) if c.is_ascii_alphabetic() => (&s[..at], c),
+        _ => (s, 's'),
+    };
+    let n: u64 = number.parse().ok()?;
+    let secs = match unit {
+        's' => n,
+        'm' => n.checked_mul(60)?,
+        'h' => n.checked_mul(60 * 60)?,
+        'd' => n.checked_mul(24 * 60 * 60)?,
+        _ => return None,
+    };
+    Some(Duration::from_secs(secs))
+}
+
+pub fn main(args: &[String]) -> i32 {
+    let now = SystemTime::now();
+    let who = env::var("USER").or_else(|_| env::var("LOGNAME")).unwrap_or_default();
+    let (file, action) = match parse_args(args, now, if who.is_empty() { "unknown" } else { &who }) {
+        Ok(parsed) => parsed,
+        Err(()) => {
+            eprintln!("{}", USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    let mut silences = match Silences::load(&file) {
+        Ok(silences) => silences,
+        Err(e) => {
+            eprintln!("silence: fatal: {}: {}", file.display(), e);
+            return EXIT_BAD_FILE;
+        }
+    };
+    silences.prune(now);
+
+    let mut rc = 0;
+    match action {
+        Action::List => {
+            for s in &silences.silences {
+                println!("{} until {}, {}", s.pattern, silence::local_time(s.until), s);
+            }
+            return rc;
+        }
+        Action::Add(s) => silences.add(s),
+        Action::Remove(pattern) => {
+            if !silences.remove(&pattern) {
+                eprintln!("silence: warning: {} is not silenced", pattern);
+                rc = EXIT_FAILURE;
+            }
+        }
+    }
+    if let Err(e) = silences.save(&file) {
+        eprintln!("silence: fatal: unable to write {}: {}", file.display(), e);
+        return EXIT_BAD_FILE;
+    }
+    rc
+}
+
+#[cfg(test)]
+use super::strings;
+
+#[test]
+fn parse_silence_args() {
+    let now = SystemTime::now();
+    let parse = |args: &[&str]| parse_args(&strings(args), now, "alice").map(|(_, a)| a);
+
+    assert_eq!(Ok(Action::List), parse(&[]));
+    assert_eq!(Ok(Action::Remove("db-*".to_string())), parse(&["-x", "db-*"]));
+    assert_eq!(Ok(Action::Add(Silence {
+                   pattern: "db-*".to_string(),
+                   until: now + Duration::from_secs(2 * 60 * 60),
+                   by: "alice".to_string(),
+                   reason: "db migration".to_string(),
+               })),
+               parse(&["-d", "2h", "db-*", "db", "migration"]));
+    match parse(&["-b", "bob", "nginx"]) {
+        Ok(Action::Add(s)) => {
+            assert_eq!("bob", s.by);
+            assert_eq!(now + DEFAULT_DURATION, s.until);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    assert!(parse(&["-d", "2h"]).is_err());
+    assert!(parse(&["-d", "2w", "db-*"]).is_err());
+    assert!(parse(&["-d", "2h", "-u", "@0", "db-*"]).is_err());
+    assert!(parse(&["-x", "db-*", "extra"]).is_err());
+    assert!(parse(&["-b", "bob smith", "db-*"]).is_err());
+    assert!(parse(&["db *", "migration"]).is_err());
+    assert!(parse(&["db-*", "migration\nstep two"]).is_err());
+    assert_eq!(Some(Duration::from_secs(90)), parse_duration("90"));
+    assert_eq!(Some(Duration::from_secs(30 * 60)), parse_duration("30m"));
+}
@@ -0,0 +1,254 @@
+//! `sv`: control and check services the way runit's `sv` does.
+//!
+//! `sv [-v] [-w SECONDS] VERB SERVICE...` sends the commands for `VERB` and,
+//! for the verbs that wait or when `-v` or `-w` is given, waits up to
+//! `SECONDS` (default 7, or `$SVWAIT`) for each service to reach the new
+//! state, printing `ok:`, `fail:`, `timeout:` or `kill:` lines. Exit codes
+//! follow the LSB init script conventions.
+
+use std::env;
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rupervise::control::{self, Command};
+use rupervise::status::{ErrorKind, ServiceDir, ServiceStatus, SvWants, SvstatError};
+
+const USAGE: &str = "usage: sv [-v] [-w sec] command service ...";
+
+const DEFAULT_WAIT: u64 = 7;
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_NOT_RUNNING: i32 = 3;
+pub const EXIT_UNKNOWN: i32 = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Verb {
+    Status,
+    Up,
+    Down,
+    Once,
+    Restart,
+    Check,
+    ForceStop,
+    ForceRestart,
+    Exit,
+}
+
+impl Verb {
+    pub fn parse(s: &str) -> Option<Verb> {
+        match s {
+            "status" | "s" => Some(Verb::Status),
+            "up" | "u" | "start" => Some(Verb::Up),
+            "down" | "d" | "stop" => Some(Verb::Down),
+            "once" | "o" => Some(Verb::Once),
+            "restart" => Some(Verb::Restart),
+            "check" | "c" => Some(Verb::Check),
+            "force-stop" => Some(Verb::ForceStop),
+            "force-restart" => Some(Verb::ForceRestart),
+            "exit" | "e" => Some(Verb::Exit),
+            _ => None,
+        }
+    }
+
+    fn commands(self) -> &'static [Command] {
+        match self {
+            Verb::Status | Verb::Check => &[],
+            Verb::Up => &[Command::Up],
+            Verb::Down | Verb::ForceStop => &[Command::Down],
+            Verb::Once => &[Command::Once],
+            Verb::Restart | Verb::ForceRestart => &[Command::Term, Command::Continue, Command::Up],
+            Verb::Exit => &[Command::Exit],
+        }
+    }
+
+    /// Whether the verb waits even without `-v`.
+    fn always_waits(self) -> bool {
+        matches!(self, Verb::Restart | Verb::Check | Verb::ForceStop | Verb::ForceRestart)
+    }
+}
+
+struct Options {
+    verbose: bool,
+    wait: Duration,
+}
+
+fn parse_args(args: &[String]) -> Result<(Options, Verb, &[String]), ()> {
+    let mut opts = Options {
+        verbose: false,
+        wait: Duration::from_secs(env::var("SVWAIT")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_WAIT)),
+    };
+    let mut i = 0;
+    while i < args.len() && args[i].starts_with('-') {
+        match args[i].as_str() {
+            "-v" => opts.verbose = true,
+            "-w" => {
+                i += 1;
+                let secs = args.get(i).and_then(|s| s.parse().ok()).ok_or(())?;
+                opts.wait = Duration::from_secs(secs);
+                opts.verbose = true;
+            }
+            _ => return Err(()),
+        }
+        i += 1;
+    }
+    let verb = args.get(i).and_then(|s| Verb::parse(s)).ok_or(())?;
+    let services = &args[i + 1..];
+    if services.is_empty() {
+        return Err(());
+    }
+    Ok((opts, verb, services))
+}
+
+pub fn main(args: &[String]) -> i32 {
+    let (opts, verb, services) = match parse_args(args) {
+        Ok(parsed) => parsed,
+        Err(()) => {
+            eprintln!("{}", USAGE);
+            return EXIT_USAGE;
+        }
+    };
+
+    let mut rc = EXIT_OK;
+    for service in services {
+        let code = if verb == Verb::Status {
+            status(Path::new(service))
+        } else {
+            act(Path::new(service), verb, &opts)
+        };
+        rc = rc.max(code);
+    }
+    rc
+}
+
+fn status(service: &Path) -> i32 {
+    match ServiceStatus::read(service) {
+        Ok(mut st) => {
+            if let Ok(dir) = ServiceDir::open(service) {
+                st.check_readiness(&dir);
+            }
+            println!("{}", st.sv_line(service));
+            if st.is_up() { EXIT_OK } else { EXIT_NOT_RUNNING }
+        }
+        Err(e) => {
+            println!("warning: {}", e);
+            EXIT_UNKNOWN
+        }
+    }
+}
+
+fn act(service: &Path, verb: Verb, opts: &Options) -> i32 {
+    let before = ServiceStatus::read(service).ok();
+    if verb != Verb::Check {
+        if let Err(e) = control::send(service, verb.commands()) {
+            println!("fail: {}", e);
+            return failure_code(&e);
+        }
+    }
+    if !opts.verbose && !verb.always_waits() {
+        return EXIT_OK;
+    }
+
+    let deadline = Instant::now() + opts.wait;
+    loop {
+        let now = ServiceStatus::read(service);
+        if reached(verb, before.as_ref(), &now) {
+            match now {
+                Ok(st) => println!("ok: {}", st.sv_line(service)),
+                Err(_) => println!("ok: {}: supervise not running", service.display()),
+            }
+            return EXIT_OK;
+        }
+        if Instant::now() >= deadline {
+            return timed_out(service, verb, now);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Whether the service reached the state `verb` asked for, given its status
+/// from before the commands were sent.
+fn reached(verb: Verb,
+           before: Option<&ServiceStatus>,
+           now: &Result<ServiceStatus, SvstatError>)
+           -> bool {
+    let st = match *now {
+        Ok(ref st) => st,
+        Err(ref e) => return verb == Verb::Exit && e.kind() == ErrorKind::SuperviseNotRunning,
+    };
+    match verb {
+        Verb::Up | Verb::Once => st.is_up(),
+        Verb::Down | Verb::ForceStop => !st.is_up(),
+        Verb::Restart | Verb::ForceRestart => {
+            st.is_up() &&
+            match before {
+                Some(b) => st.since > b.since,
+                None => true,
+            }
+        }
+        Verb::Check => match st.wants {
+            Some(SvWants::WantsDown) => !st.is_up(),
+            _ => st.is_up(),
+        },
+        Verb::Status | Verb::Exit => false,
+    }
+}
+
+fn timed_out(service: &Path, verb: Verb, now: Result<ServiceStatus, SvstatError>) -> i32 {
+    let line = match now {
+        Ok(ref st) => st.sv_line(service).to_string(),
+        Err(ref e) => e.to_string(),
+    };
+    match verb {
+        Verb::ForceStop | Verb::ForceRestart => {
+            println!("kill: {}", line);
+            match control::send(service, &[Command::Kill]) {
+                Ok(()) => EXIT_OK,
+                Err(e) => {
+                    println!("fail: {}", e);
+                    failure_code(&e)
+                }
+            }
+        }
+        _ => {
+            println!("timeout: {}", line);
+            EXIT_FAILURE
+        }
+    }
+}
+
+/// LSB exit code 4 means "insufficient privilege"; anything else is a
+/// generic failure.
+fn failure_code(e: &SvstatError) -> i32 {
+    match e.io_error().map(io::Error::kind) {
+        Some(io::ErrorKind::PermissionDenied) => EXIT_UNKNOWN,
+        _ => EXIT_FAILURE,
+    }
+}
+
+#[cfg(test)]
+use super::strings;
+
+#[test]
+fn parse_wait_implies_verbose() {
+    let args = strings(&["-w", "30", "restart", "/service/a"]);
+    let (opts, verb, services) = parse_args(&args).unwrap();
+    assert!(opts.verbose);
+    assert_eq!(Duration::from_secs(30), opts.wait);
+    assert_eq!(Verb::Restart, verb);
+    assert_eq!(&strings(&["/service/a"])[..], services);
+}
+
+#[test]
+fn parse_rejects_bad_usage() {
+    assert!(parse_args(&strings(&["frobnicate", "/service/a"])).is_err());
+    assert!(parse_args(&strings(&["up"])).is_err());
+    assert!(parse_args(&strings(&["-w", "soon", "up", "/service/a"])).is_err());
+}
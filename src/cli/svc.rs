@@ -0,0 +1,93 @@
+//! `svc`: send commands to supervised services.
+//!
+//! Accepts the daemontools options and applies them, in the order first
+//! given, to each service directory. Exits 100 on a usage error and 0
+//! otherwise; failures to control a service are warnings, as in `svc`.
+
+use rupervise::control::Command;
+use rupervise::status::ErrorKind;
+
+const USAGE: &str = "svc options: u up, d down, o once, x exit, p pause, c continue, \
+                     h hup, a alarm, i interrupt, t term, k kill";
+
+pub const EXIT_USAGE: i32 = 100;
+
+/// Splits `svc`-style arguments into commands and service directories.
+///
+/// Options may be bundled (`-dx`) and end at `--` or at the first argument
+/// that does not start with `-`. A command given twice is sent once.
+pub fn parse_args(args: &[String]) -> Result<(Vec<Command>, &[String]), ()> {
+    let mut commands = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        if arg == "--" {
+            i += 1;
+            break;
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            break;
+        }
+        for &b in &arg.as_bytes()[1..] {
+            let command = Command::from_byte(b).ok_or(())?;
+            if !commands.contains(&command) {
+                commands.push(command);
+            }
+        }
+        i += 1;
+    }
+    Ok((commands, &args[i..]))
+}
+
+pub fn main(args: &[String]) -> i32 {
+    let (commands, dirs) = match parse_args(args) {
+        Ok((commands, dirs)) if !dirs.is_empty() => (commands, dirs),
+        _ => {
+            eprintln!("{}", USAGE);
+            return EXIT_USAGE;
+        }
+    };
+
+    for dir in dirs {
+        if let Err(e) = ::rupervise::control::send(dir, &commands) {
+            let cause = e.io_error().map(|e| e.to_string()).unwrap_or_default();
+            match e.kind() {
+                ErrorKind::SuperviseNotRunning => {
+                    eprintln!("svc: warning: unable to control {}: supervise not running", dir)
+                }
+                ErrorKind::UnableToOpenDir => {
+                    eprintln!("svc: warning: unable to open {}: {}", dir, cause)
+                }
+                ErrorKind::UnableToWriteSuperviseControl => {
+                    eprintln!("svc: warning: error writing commands to {}: {}", dir, cause)
+                }
+                _ => eprintln!("svc: warning: unable to control {}: {}", dir, cause),
+            }
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+use super::strings;
+
+#[test]
+fn parse_bundled_and_repeated_options() {
+    let args = strings(&["-dx", "-t", "-d", "/service/a", "/service/b"]);
+    let (commands, dirs) = parse_args(&args).unwrap();
+    assert_eq!(vec![Command::Down, Command::Exit, Command::Term], commands);
+    assert_eq!(&strings(&["/service/a", "/service/b"])[..], dirs);
+}
+
+#[test]
+fn parse_stops_at_double_dash() {
+    let args = strings(&["-u", "--", "-weird-dir"]);
+    let (commands, dirs) = parse_args(&args).unwrap();
+    assert_eq!(vec![Command::Up], commands);
+    assert_eq!(&strings(&["-weird-dir"])[..], dirs);
+}
+
+#[test]
+fn parse_rejects_unknown_option() {
+    assert!(parse_args(&strings(&["-z", "/service/a"])).is_err());
+}
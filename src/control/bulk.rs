@@ -0,0 +1,170 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use status::{ErrorKind, ServiceStatus, SvstatError};
+use wait::{self, Condition, Mode};
+
+use super::{send, Command};
+
+/// How [`send_all`] spreads its work.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BulkOptions {
+    /// The most services controlled at once.
+    pub jobs: usize,
+    /// After sending, wait this long for each service to meet the condition.
+    pub wait: Option<(Condition, Duration)>,
+}
+
+impl Default for BulkOptions {
+    fn default() -> BulkOptions {
+        BulkOptions {
+            jobs: 16,
+            wait: None,
+        }
+    }
+}
+
+/// What happened to one service.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SendResult {
+    /// The commands were written, and the wait, if any, succeeded.
+    Sent,
+    NotRunning,
+    PermissionDenied,
+    /// The commands were written but the service did not reach the state
+    /// waited for.
+    TimedOut,
+    Failed(SvstatError),
+}
+
+impl SendResult {
+    fn from_error(e: SvstatError) -> SendResult {
+        if e.kind() == ErrorKind::SuperviseNotRunning {
+            return SendResult::NotRunning;
+        }
+        match e.io_error().map(io::Error::kind) {
+            Some(io::ErrorKind::PermissionDenied) => SendResult::PermissionDenied,
+            _ => SendResult::Failed(e),
+        }
+    }
+}
+
+impl fmt::Display for SendResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SendResult::Sent => write!(f, "sent"),
+            SendResult::NotRunning => write!(f, "not running"),
+            SendResult::PermissionDenied => write!(f, "permission denied"),
+            SendResult::TimedOut => write!(f, "timed out"),
+            SendResult::Failed(ref e) => write!(f, "failed: {}", e),
+        }
+    }
+}
+
+/// Sends `commands` to every service in `paths`, up to `opts.jobs` at a
+/// time. Results come back in the order of `paths`.
+pub fn send_all<P>(paths: &[P], commands: &[Command], opts: &BulkOptions)
+                   -> Vec<(PathBuf, SendResult)>
+    where P: AsRef<Path> + Sync
+{
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<SendResult>>> = Mutex::new(vec![None; paths.len()]);
+
+    thread::scope(|s| {
+        for _ in 0..opts.jobs.clamp(1, paths.len().max(1)) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= paths.len() {
+                    break;
+                }
+                let result = send_one(paths[i].as_ref(), commands, opts.wait);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+
+    let results = results.into_inner().unwrap();
+    paths.iter()
+        .zip(results)
+        .map(|(p, r)| (p.as_ref().to_path_buf(), r.expect("every service was sent to")))
+        .collect()
+}
+
+fn send_one(path: &Path, commands: &[Command], wait: Option<(Condition, Duration)>) -> SendResult {
+    // Take the pid before sending: a quick restart can be over before the
+    // wait would first read it.
+    let wait = wait.map(|(condition, timeout)| match condition {
+        Condition::PidChanged => (Condition::PidChangedFrom(up_pid(path)), timeout),
+        other => (other, timeout),
+    });
+    if let Err(e) = send(path, commands) {
+        return SendResult::from_error(e);
+    }
+    match wait {
+        Some((condition, timeout)) => match wait::wait(&[path], condition, Mode::All, timeout) {
+            Ok(_) => SendResult::Sent,
+            Err(_) => SendResult::TimedOut,
+        },
+        None => SendResult::Sent,
+    }
+}
+
+/// The pid of the service at `path` if it is up.
+fn up_pid(path: &Path) -> Option<u32> {
+    match ServiceStatus::read(path) {
+        Ok(ref st) if st.is_up() => st.pid,
+        _ => None,
+    }
+}
+
+#[test]
+fn send_all_keeps_order() {
+    use testing::{fake_service, open_fifo};
+
+    let running = fake_service("bulk-running");
+    let stopped = fake_service("bulk-stopped");
+    let _reader = open_fifo(&running, "supervise/control");
+    let missing = ::std::env::temp_dir().join("rupervise-bulk-missing");
+
+    let opts = BulkOptions { jobs: 2, wait: None };
+    let results = send_all(&[&stopped, &running, &missing], &[Command::Up], &opts);
+
+    assert_eq!(SendResult::NotRunning, results[0].1);
+    assert_eq!((running.clone(), SendResult::Sent), results[1]);
+    match results[2].1 {
+        SendResult::Failed(ref e) => assert_eq!(ErrorKind::UnableToOpenDir, e.kind()),
+        ref other => panic!("unexpected result {:?}", other),
+    }
+    ::std::fs::remove_dir_all(&running).unwrap();
+    ::std::fs::remove_dir_all(&stopped).unwrap();
+}
+
+#[test]
+fn waits_for_a_quick_restart() {
+    use testing::{fake_service, fake_supervise, open_fifo, write_status};
+
+    let path = fake_service("bulk-restart");
+    let _ok = open_fifo(&path, "supervise/ok");
+    write_status(&path, 100);
+
+    // Restarts the moment it is told to, as a service that dies on TERM
+    // and is started again straight away would.
+    let supervise = {
+        let dir = path.clone();
+        fake_supervise(&path, b't', move || write_status(&dir, 200))
+    };
+
+    let opts = BulkOptions {
+        jobs: 1,
+        wait: Some((Condition::PidChanged, Duration::from_secs(2))),
+    };
+    let results = send_all(&[&path], &[Command::Term], &opts);
+    assert_eq!(b"t".to_vec(), supervise.join().unwrap());
+    assert_eq!(SendResult::Sent, results[0].1);
+    ::std::fs::remove_dir_all(&path).unwrap();
+}
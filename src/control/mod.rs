@@ -0,0 +1,176 @@
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use libc;
+
+use status::{ErrorKind, NotRunning, Service, ServiceDir, SvstatError};
+
+mod bulk;
+mod stop;
+pub use self::bulk::{send_all, BulkOptions, SendResult};
+pub use self::stop::{stop, StopOptions, StopOutcome, StopReport};
+
+/// A command understood by `supervise/control`, as sent by `svc`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Command {
+    Up,
+    Down,
+    Once,
+    Pause,
+    Continue,
+    Hup,
+    Alarm,
+    Interrupt,
+    Term,
+    Kill,
+    Exit,
+}
+
+impl Command {
+    /// The byte written to the control FIFO, which is also the `svc` option
+    /// letter.
+    pub fn as_byte(self) -> u8 {
+        match self {
+            Command::Up => b'u',
+            Command::Down => b'd',
+            Command::Once => b'o',
+            Command::Pause => b'p',
+            Command::Continue => b'c',
+            Command::Hup => b'h',
+            Command::Alarm => b'a',
+            Command::Interrupt => b'i',
+            Command::Term => b't',
+            Command::Kill => b'k',
+            Command::Exit => b'x',
+        }
+    }
+
+    pub fn from_byte(b: u8) -> Option<Command> {
+        match b {
+            b'u' => Some(Command::Up),
+            b'd' => Some(Command::Down),
+            b'o' => Some(Command::Once),
+            b'p' => Some(Command::Pause),
+            b'c' => Some(Command::Continue),
+            b'h' => Some(Command::Hup),
+            b'a' => Some(Command::Alarm),
+            b'i' => Some(Command::Interrupt),
+            b't' => Some(Command::Term),
+            b'k' => Some(Command::Kill),
+            b'x' => Some(Command::Exit),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Command::Up => "up",
+            Command::Down => "down",
+            Command::Once => "once",
+            Command::Pause => "pause",
+            Command::Continue => "continue",
+            Command::Hup => "hup",
+            Command::Alarm => "alarm",
+            Command::Interrupt => "interrupt",
+            Command::Term => "term",
+            Command::Kill => "kill",
+            Command::Exit => "exit",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl ServiceDir {
+    /// Writes `commands` to `supervise/control` in a single write, the way
+    /// `svc` does.
+    pub fn send(&self, commands: &[Command]) -> Result<(), SvstatError> {
+        let fail = |kind, e| SvstatError::with_source(kind, self.path(), e);
+
+        let mut control = match self.open_write("supervise/control") {
+            Ok(control) => control,
+            Err(e) => {
+                if e.raw_os_error() == Some(libc::ENXIO) {
+                    return Err(SvstatError::not_running(self.path(), NotRunning::NoReader, Some(e)));
+                }
+                return Err(fail(ErrorKind::UnableToOpenSuperviseControl, e));
+            }
+        };
+        if let Err(e) = set_blocking(&control) {
+            return Err(fail(ErrorKind::UnableToWriteSuperviseControl, e));
+        }
+
+        let bytes: Vec<u8> = commands.iter().map(|c| c.as_byte()).collect();
+        control.write_all(&bytes)
+            .map_err(|e| fail(ErrorKind::UnableToWriteSuperviseControl, e))
+    }
+}
+
+impl Service {
+    /// Sends `commands` to the supervisor of this service.
+    pub fn send(&self, commands: &[Command]) -> Result<(), SvstatError> {
+        send(&self.name, commands)
+    }
+}
+
+/// Sends `commands` to the supervisor of the service directory at `path`.
+pub fn send<P: AsRef<Path>>(path: P, commands: &[Command]) -> Result<(), SvstatError> {
+    let path = path.as_ref();
+    match ServiceDir::open(path) {
+        Ok(dir) => dir.send(commands),
+        Err(e) => Err(SvstatError::with_source(ErrorKind::UnableToOpenDir, path, e)),
+    }
+}
+
+fn set_blocking<F: AsRawFd>(f: &F) -> io::Result<()> {
+    let fd = f.as_raw_fd();
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags == -1 || libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_NONBLOCK) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn command_bytes_round_trip() {
+    for &b in b"udopchaitkx" {
+        assert_eq!(b, Command::from_byte(b).unwrap().as_byte());
+    }
+    assert_eq!(None, Command::from_byte(b'z'));
+}
+
+#[test]
+fn send_writes_control_bytes() {
+    use testing::{fake_service, open_fifo};
+
+    let path = fake_service("send");
+    let mut reader = open_fifo(&path, "supervise/control");
+
+    Service::new(&path).send(&[Command::Down, Command::Term, Command::Continue]).unwrap();
+
+    let mut buf = [0u8; 8];
+    let n = reader.read(&mut buf).unwrap();
+    assert_eq!(b"dtc", &buf[..n]);
+    ::std::fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn send_without_supervise() {
+    use testing::fake_service;
+
+    let path = fake_service("noreader");
+
+    let e = send(&path, &[Command::Up]).unwrap_err();
+    assert_eq!(ErrorKind::SuperviseNotRunning, e.kind());
+
+    ::std::fs::remove_file(path.join("supervise/control")).unwrap();
+    let e = send(&path, &[Command::Up]).unwrap_err();
+    assert_eq!(ErrorKind::UnableToOpenSuperviseControl, e.kind());
+    ::std::fs::remove_dir_all(&path).unwrap();
+}
@@ -0,0 +1,207 @@
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use libc;
+
+use status::{ServiceStatus, SvWants, SvstatError, SvstatType};
+use wait::{self, Condition, Mode};
+
+use super::{send, Command};
+
+/// How long [`stop`] gives a service at each step.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StopOptions {
+    /// How long to wait after TERM before sending KILL.
+    pub grace: Duration,
+    /// How long to wait after KILL before giving up.
+    pub kill_wait: Duration,
+    /// Also KILL the process group the service leads, catching children
+    /// it left behind.
+    pub kill_group: bool,
+}
+
+impl Default for StopOptions {
+    fn default() -> StopOptions {
+        StopOptions {
+            grace: Duration::from_secs(7),
+            kill_wait: Duration::from_secs(3),
+            kill_group: false,
+        }
+    }
+}
+
+/// What it took to stop a service.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StopOutcome {
+    /// The service was not running; it was only told to stay down.
+    AlreadyDown,
+    /// The service exited after TERM.
+    Terminated,
+    /// The service had to be sent KILL.
+    Killed,
+    /// The service and its process group had to be sent KILL.
+    KilledGroup,
+    /// The service was still up after KILL.
+    StillRunning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StopReport {
+    /// The pid that was running when the stop started.
+    pub pid: Option<u32>,
+    pub outcome: StopOutcome,
+    /// The last status read.
+    pub status: ServiceStatus,
+    pub elapsed: Duration,
+}
+
+impl StopReport {
+    pub fn is_stopped(&self) -> bool {
+        self.outcome != StopOutcome::StillRunning
+    }
+}
+
+impl fmt::Display for StopReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let what = match self.outcome {
+            StopOutcome::AlreadyDown => "already down",
+            StopOutcome::Terminated => "stopped by TERM",
+            StopOutcome::Killed => "stopped by KILL",
+            StopOutcome::KilledGroup => "stopped by KILL to the process group",
+            StopOutcome::StillRunning => "still running after KILL",
+        };
+        write!(f, "{}", what)?;
+        if let Some(pid) = self.pid {
+            write!(f, " (pid {})", pid)?;
+        }
+        write!(f, " in {}.{:03}s", self.elapsed.as_secs(), self.elapsed.subsec_millis())?;
+        if self.status.wants == Some(SvWants::WantsUp) {
+            write!(f, ", but wants up")?;
+        }
+        Ok(())
+    }
+}
+
+/// Stops the service at `path`, escalating from TERM to KILL.
+///
+/// Sends down and TERM and waits up to `opts.grace` for the pid to go away,
+/// then sends KILL and waits up to `opts.kill_wait`. Each step is confirmed
+/// from the status file rather than assumed.
+pub fn stop<P: AsRef<Path>>(path: P, opts: &StopOptions) -> Result<StopReport, SvstatError> {
+    let path = path.as_ref();
+    let started = Instant::now();
+    let report = |pid, outcome, status| {
+        StopReport {
+            pid,
+            outcome,
+            status,
+            elapsed: started.elapsed(),
+        }
+    };
+
+    let before = ServiceStatus::read(path)?;
+    let pid = if before.is_up() { before.pid } else { None };
+    if pid.is_none() {
+        send(path, &[Command::Down])?;
+        let status = ServiceStatus::read(path)?;
+        return Ok(report(None, StopOutcome::AlreadyDown, status));
+    }
+
+    send(path, &[Command::Down, Command::Term, Command::Continue])?;
+    if let Some(status) = wait_down(path, opts.grace)? {
+        return Ok(report(pid, StopOutcome::Terminated, status));
+    }
+
+    // The group goes first: once the supervisor has killed and reaped the
+    // leader, its pid no longer tells us the group.
+    let mut outcome = StopOutcome::Killed;
+    if opts.kill_group {
+        if let Some(pid) = pid {
+            if kill_group(pid) {
+                outcome = StopOutcome::KilledGroup;
+            }
+        }
+    }
+    send(path, &[Command::Kill])?;
+    match wait_down(path, opts.kill_wait)? {
+        Some(status) => Ok(report(pid, outcome, status)),
+        None => Ok(report(pid, StopOutcome::StillRunning, ServiceStatus::read(path)?)),
+    }
+}
+
+/// Waits for the service to be down, returning the status that showed it,
+/// or `None` on timeout.
+fn wait_down(path: &Path, timeout: Duration) -> Result<Option<ServiceStatus>, SvstatError> {
+    let services = match wait::wait(&[path], Condition::Down, Mode::All, timeout) {
+        Ok(services) => services,
+        Err(timed_out) => match timed_out.services.into_iter().next().and_then(|s| s.status) {
+            Some(SvstatType::SvError(e)) => return Err(e),
+            _ => return Ok(None),
+        },
+    };
+    match services.into_iter().next().and_then(|s| s.status) {
+        Some(SvstatType::SvOk(status)) => Ok(Some(status)),
+        Some(SvstatType::SvError(e)) => Err(e),
+        None => Ok(None),
+    }
+}
+
+/// Sends KILL to the process group led by `pid`. Does nothing, and returns
+/// false, unless `pid` is the group leader: a service that never called
+/// `setsid` shares its group with the supervisor.
+fn kill_group(pid: u32) -> bool {
+    let pid = pid as libc::pid_t;
+    unsafe { libc::getpgid(pid) == pid && libc::kill(-pid, libc::SIGKILL) == 0 }
+}
+
+#[test]
+fn report_display() {
+    use testing;
+
+    let status = ServiceStatus { wants: Some(SvWants::WantsDown), ..testing::status(None) };
+    let report = StopReport {
+        pid: Some(42),
+        outcome: StopOutcome::Killed,
+        status,
+        elapsed: Duration::from_millis(7250),
+    };
+    assert_eq!("stopped by KILL (pid 42) in 7.250s", report.to_string());
+    assert!(report.is_stopped());
+}
+
+#[test]
+fn escalates_to_killing_the_group() {
+    use std::os::unix::process::CommandExt;
+    use std::process;
+    use testing::{fake_service, fake_supervise, open_fifo, write_status};
+
+    let path = fake_service("stop");
+    let _ok = open_fifo(&path, "supervise/ok");
+    let mut service = process::Command::new("sleep").arg("30").process_group(0).spawn().unwrap();
+    let pid = service.id();
+    write_status(&path, pid);
+
+    // A supervisor whose service ignores TERM: only KILL, which it sends
+    // to the leader and reaps, brings it down.
+    let supervise = {
+        let dir = path.clone();
+        fake_supervise(&path, b'k', move || {
+            let _ = service.kill();
+            service.wait().unwrap();
+            write_status(&dir, 0);
+        })
+    };
+
+    let opts = StopOptions {
+        grace: Duration::from_millis(100),
+        kill_wait: Duration::from_secs(2),
+        kill_group: true,
+    };
+    let report = stop(&path, &opts).unwrap();
+    assert_eq!(b"dtck".to_vec(), supervise.join().unwrap());
+    assert_eq!(Some(pid), report.pid);
+    assert_eq!(StopOutcome::KilledGroup, report.outcome);
+    assert!(report.is_stopped());
+    ::std::fs::remove_dir_all(&path).unwrap();
+}
@@ -0,0 +1,275 @@
+//! Running commands when services change state.
+//!
+//! When a service comes up, goes down or restarts, the executable
+//! `hooks/on-up`, `hooks/on-down` or `hooks/on-restart` in its directory is
+//! run, then the global hook if one is configured. Hooks run one at a time,
+//! in the service directory, with the details of the change in their
+//! environment:
+//!
+//! - `HOOK`: `on-up`, `on-down` or `on-restart`
+//! - `SERVICE`: the service directory
+//! - `OLD_STATE`, `NEW_STATE`: `up`, `down`, `finish`, `not running` or
+//!   `unknown`
+//! - `PID`: the new pid, or the pid that exited when the service went down
+//! - `DURATION`: seconds the service had spent in `OLD_STATE`, from the
+//!   stamp of the old state to that of the new one, or to when the change
+//!   was seen if the new state could not be read
+//! - `NORMALLY_UP`: `1` or `0`
+//!
+//! A hook that runs longer than the timeout is killed, along with anything
+//! it started, as each hook runs in a process group of its own. Relative
+//! service and hook paths are resolved against the current directory before
+//! the hook is started in the service directory.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libc;
+
+use status::{ErrorKind, RunState, SvstatType, Transition};
+use watch::Event;
+
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Only one hook runs at a time in the whole process.
+static RUNNING: Mutex<()> = Mutex::new(());
+
+/// Which hooks to run and for how long.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hooks {
+    /// Run for every up, down or restart, after the service's own hook.
+    pub global: Option<PathBuf>,
+    pub timeout: Duration,
+}
+
+impl Default for Hooks {
+    fn default() -> Hooks {
+        Hooks {
+            global: None,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// How a hook run ended.
+#[derive(Debug)]
+pub enum HookResult {
+    Exited(ExitStatus),
+    TimedOut,
+    Failed(io::Error),
+}
+
+/// One hook that was run.
+#[derive(Debug)]
+pub struct HookRun {
+    pub hook: PathBuf,
+    pub result: HookResult,
+}
+
+impl HookRun {
+    pub fn is_success(&self) -> bool {
+        match self.result {
+            HookResult::Exited(status) => status.success(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for HookRun {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.hook.display())?;
+        match self.result {
+            HookResult::Exited(status) => write!(f, "{}", status),
+            HookResult::TimedOut => write!(f, "timed out"),
+            HookResult::Failed(ref e) => write!(f, "unable to run: {}", e),
+        }
+    }
+}
+
+impl Hooks {
+    /// Runs the hooks for each transition in `event`, one after another.
+    /// Hooks that do not exist are skipped.
+    pub fn run(&self, event: &Event) -> Vec<HookRun> {
+        let mut runs = Vec::new();
+        let service = absolute(&event.service);
+        let global = self.global.as_ref().map(|g| absolute(g));
+        for t in event.transitions() {
+            let name = match t {
+                Transition::Up { .. } => "on-up",
+                Transition::Down { .. } => "on-down",
+                Transition::Restarted { .. } => "on-restart",
+                _ => continue,
+            };
+            let own = service.join("hooks").join(name);
+            let hooks = Some(own).filter(|h| h.is_file()).into_iter().chain(global.clone());
+            for hook in hooks {
+                let result = self.run_one(&hook, &service, name, &t, event);
+                runs.push(HookRun { hook, result });
+            }
+        }
+        runs
+    }
+
+    /// Runs hooks on a thread of their own, in the order events are sent,
+    /// and sends each run down `runs` once it is over.
+    pub fn spawn(self, runs: mpsc::Sender<HookRun>) -> mpsc::Sender<Event> {
+        let (tx, rx) = mpsc::channel::<Event>();
+        thread::spawn(move || {
+            for event in rx {
+                for run in self.run(&event) {
+                    let _ = runs.send(run);
+                }
+            }
+        });
+        tx
+    }
+
+    fn run_one(&self,
+               hook: &Path,
+               service: &Path,
+               name: &str,
+               t: &Transition,
+               event: &Event)
+               -> HookResult {
+        let _running = RUNNING.lock().unwrap_or_else(|e| e.into_inner());
+
+        let pid = match *t {
+            Transition::Up { pid } | Transition::Down { pid } => pid,
+            Transition::Restarted { new_pid, .. } => new_pid,
+            _ => 0,
+        };
+        let (duration, normally_up) = match (&event.old, &event.new) {
+            (Some(SvstatType::SvOk(old)), SvstatType::SvOk(new)) => {
+                (new.since.duration_since(old.since), new.normally_up)
+            }
+            (Some(SvstatType::SvOk(old)), SvstatType::SvError(_)) => {
+                (event.at.duration_since(old.since), old.normally_up)
+            }
+            (_, SvstatType::SvOk(new)) => (None, new.normally_up),
+            (_, SvstatType::SvError(_)) => (None, false),
+        };
+        let duration = duration.map_or(0, |d| d.as_secs());
+
+        let child = Command::new(hook)
+            .process_group(0)
+            .current_dir(service)
+            .stdin(Stdio::null())
+            .env("HOOK", name)
+            .env("SERVICE", service)
+            .env("OLD_STATE", event.old.as_ref().map_or("unknown", state_name))
+            .env("NEW_STATE", state_name(&event.new))
+            .env("PID", pid.to_string())
+            .env("DURATION", duration.to_string())
+            .env("NORMALLY_UP", if normally_up { "1" } else { "0" })
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => return HookResult::Failed(e),
+        };
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            match child.try_wait() {
+                Ok(Some(status)) => return HookResult::Exited(status),
+                Ok(None) if Instant::now() < deadline => thread::sleep(WAIT_POLL_INTERVAL),
+                Ok(None) => {
+                    // The group is the hook's own, so this takes anything
+                    // it forked too, such as the rest of a pipeline.
+                    unsafe { libc::killpg(child.id() as libc::pid_t, libc::SIGKILL) };
+                    let _ = child.wait();
+                    return HookResult::TimedOut;
+                }
+                Err(e) => return HookResult::Failed(e),
+            }
+        }
+    }
+}
+
+/// `path` made absolute, so it means the same thing from the service
+/// directory the hook runs in.
+fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| match env::current_dir() {
+        Ok(cwd) => cwd.join(path),
+        Err(_) => path.to_path_buf(),
+    })
+}
+
+fn state_name(status: &SvstatType) -> &'static str {
+    match *status {
+        SvstatType::SvOk(ref st) => match st.run_state {
+            RunState::Run => "up",
+            RunState::Down => "down",
+            RunState::Finish => "finish",
+        },
+        SvstatType::SvError(ref e) if e.kind() == ErrorKind::SuperviseNotRunning => "not running",
+        SvstatType::SvError(_) => "unknown",
+    }
+}
+
+#[test]
+fn runs_service_hook_with_environment() {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use status::ServiceStatus;
+    use tai::Tain;
+    use testing;
+
+    // Relative to the crate, where tests run, and nowhere else.
+    let path = PathBuf::from(format!("target/rupervise-hooks-{}", ::std::process::id()));
+    fs::create_dir_all(path.join("hooks")).unwrap();
+    let hook = path.join("hooks/on-down");
+    fs::write(&hook,
+              "#!/bin/sh\necho \"$HOOK $OLD_STATE $NEW_STATE $PID $DURATION $NORMALLY_UP\" > seen\n")
+        .unwrap();
+    fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let now = Tain::now();
+    // Each read just after its state began, so `duration` is 0 and not
+    // what DURATION reports.
+    let status = |pid, since| SvstatType::SvOk(ServiceStatus { since, ..testing::status(pid) });
+    let event = Event {
+        index: 0,
+        service: path.clone(),
+        old: Some(status(Some(123), now - Duration::from_secs(3600))),
+        new: status(None, now),
+        at: now,
+    };
+
+    let runs = Hooks::default().run(&event);
+    assert_eq!(1, runs.len());
+    assert!(runs[0].is_success(), "{}", runs[0]);
+    assert_eq!("on-down up down 123 3600 1\n", fs::read_to_string(path.join("seen")).unwrap());
+
+    let global = path.join("slow");
+    fs::write(&global, "#!/bin/sh\nsleep 5 &\necho $! > background\nwait\n").unwrap();
+    fs::set_permissions(&global, fs::Permissions::from_mode(0o755)).unwrap();
+    let slow = Hooks {
+        global: Some(global.clone()),
+        timeout: Duration::from_millis(100),
+    };
+    let runs = slow.run(&Event { new: status(Some(124), now), ..event });
+    assert_eq!(1, runs.len());
+    assert_eq!(absolute(&global), runs[0].hook);
+    match runs[0].result {
+        HookResult::TimedOut => {}
+        ref other => panic!("unexpected result {:?}", other),
+    }
+    // What the hook left running went with it, leaving at most a zombie
+    // for init to reap.
+    let background = fs::read_to_string(path.join("background")).unwrap();
+    let stat = format!("/proc/{}/stat", background.trim());
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while fs::read_to_string(&stat).is_ok_and(|s| !s.contains(") Z ")) {
+        assert!(Instant::now() < deadline, "hook left {} running", background.trim());
+        thread::sleep(WAIT_POLL_INTERVAL);
+    }
+    fs::remove_dir_all(&path).unwrap();
+}
@@ -0,0 +1,136 @@
+//! A minimal inotify wrapper for watching supervise directories.
+
+use std::cmp;
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use libc;
+
+/// Size of `struct inotify_event` without the trailing name.
+const EVENT_HEADER_LEN: usize = 16;
+
+/// A change to a file in a watched directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// The watch descriptor `add_watch` returned for the directory.
+    pub wd: i32,
+    pub mask: u32,
+    /// The name of the file within the directory.
+    pub name: Vec<u8>,
+}
+
+/// A non-blocking inotify instance.
+pub struct Inotify {
+    fd: File,
+}
+
+impl Inotify {
+    pub fn new() -> io::Result<Inotify> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Inotify { fd: unsafe { File::from_raw_fd(fd) } })
+    }
+
+    /// Watches `path` for the events in `mask`, returning the watch
+    /// descriptor events on it will carry.
+    pub fn add_watch(&self, path: &Path, mask: u32) -> io::Result<i32> {
+        let path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), mask) };
+        if wd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(wd)
+    }
+
+    /// Waits up to `timeout` for events and returns every one pending, or
+    /// none if the timeout expired first.
+    pub fn wait(&mut self, timeout: Duration) -> io::Result<Vec<Event>> {
+        if !self.poll(timeout)? {
+            return Ok(Vec::new());
+        }
+        self.drain()
+    }
+
+    /// Waits up to `timeout` for an event on a file called `name` in any
+    /// watched directory. Returns whether one arrived.
+    ///
+    /// Events on other files are read and dropped without returning, so
+    /// opening `supervise/ok` does not wake the caller.
+    pub fn wait_for(&mut self, name: &[u8], timeout: Duration) -> io::Result<bool> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(false);
+            }
+            let events = self.wait(deadline - now)?;
+            if events.is_empty() {
+                return Ok(false);
+            }
+            if events.iter().any(|e| e.name == name) {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn poll(&self, timeout: Duration) -> io::Result<bool> {
+        let mut pfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // Round up so a sub-millisecond wait does not spin.
+        let ms = timeout.saturating_add(Duration::from_nanos(999_999)).as_millis();
+        let ms = cmp::min(ms, libc::c_int::MAX as u128);
+        match unsafe { libc::poll(&mut pfd, 1, ms as libc::c_int) } {
+            -1 => {
+                let e = io::Error::last_os_error();
+                if e.kind() == io::ErrorKind::Interrupted { Ok(false) } else { Err(e) }
+            }
+            0 => Ok(false),
+            _ => Ok(true),
+        }
+    }
+
+    /// Reads every pending event.
+    fn drain(&mut self) -> io::Result<Vec<Event>> {
+        let mut buf = [0u8; 4096];
+        let mut found = Vec::new();
+        loop {
+            let n = match self.fd.read(&mut buf) {
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(found),
+                Err(e) => return Err(e),
+            };
+            let mut events = &buf[..n];
+            while events.len() >= EVENT_HEADER_LEN {
+                let field = |at: usize| {
+                    let mut bytes = [0; 4];
+                    bytes.copy_from_slice(&events[at..at + 4]);
+                    u32::from_ne_bytes(bytes)
+                };
+                let end = cmp::min(EVENT_HEADER_LEN + field(12) as usize, events.len());
+                let name = &events[EVENT_HEADER_LEN..end];
+                let name = match name.iter().position(|&b| b == 0) {
+                    Some(nul) => &name[..nul],
+                    None => name,
+                };
+                found.push(Event {
+                    wd: field(0) as i32,
+                    mask: field(4),
+                    name: name.to_vec(),
+                });
+                events = &events[end..];
+            }
+        }
+    }
+}
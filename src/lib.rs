@@ -1 +1,15 @@
+extern crate libc;
+extern crate serde_json;
+
+pub mod control;
+pub mod hooks;
+mod inotify;
+pub mod reconcile;
+pub mod silence;
+pub mod status;
 pub mod tai;
+#[cfg(test)]
+mod testing;
+pub mod wait;
+pub mod watch;
+pub mod webhook;
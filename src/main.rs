@@ -1,223 +1,172 @@
 use std::env;
-use std::error::Error;
-use std::fs::{File, OpenOptions};
-use std::io::prelude::*;
-use std::io;
 use std::path::{Path, PathBuf};
-
-extern crate libc;
-use std::os::unix::fs::OpenOptionsExt;
+use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 extern crate rupervise;
-use rupervise::tai::*;
-
-#[derive(Debug, Copy, Clone)]
-enum SvstatError {
-    UnableToChDir,
-    UnableToStatDown,
-    SuperviseNotRunning,
-    UnableToOpenSuperviseOk,
-    UnableToOpenSuperviseStatus,
-    StatusBadFormat,
-    StatusOtherError,
-}
-
-#[derive(Debug, Copy, Clone, PartialEq)]
-enum SvWants {
-    WantsUp,
-    WantsDown,
-}
-
-#[derive(Debug)]
-enum SvstatType {
-    SvError(SvstatError),
-    SvOk {
-        pid: Option<u32>,
-        normally_up: bool,
-        is_paused: bool,
-        duration: u64,
-        wants: Option<SvWants>,
-    },
-}
+use rupervise::hooks::{HookRun, Hooks};
+use rupervise::silence::{self, Silences};
+use rupervise::status::{CrashLoopThreshold, RestartHistory, Service};
+use rupervise::watch::Watcher;
+use rupervise::webhook::{self, Notification, Url, Webhook};
 
-#[derive(Debug)]
-struct Service {
-    name: PathBuf,
-    status: Option<SvstatType>,
-}
+mod cli;
 
-impl std::fmt::Display for Service {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self.status {
-            Some(SvstatType::SvOk { pid: Some(p),
-                                    normally_up: nu,
-                                    is_paused: ip,
-                                    duration: d,
-                                    wants: w }) => {
-                write!(f, "{}: up (pid {}) {} seconds", self.name.display(), p, d);
-                if !nu {
-                    write!(f, ", normally down");
-                }
-                if ip {
-                    write!(f, ", paused");
-                }
-                if w == Some(SvWants::WantsDown) {
-                    write!(f, ", want down");
-                }
-                write!(f, "")
+fn main() {
+    let args: Vec<String> = env::args().collect();
 
-            }
-            Some(SvstatType::SvOk { pid: None,
-                                    normally_up: nu,
-                                    is_paused: ip,
-                                    duration: d,
-                                    wants: w }) => {
-                write!(f, "{}: down {} seconds", self.name.display(), d);
-                if nu {
-                    write!(f, ", normally up");
-                }
-                if w == Some(SvWants::WantsUp) {
-                    write!(f, ", want up");
-                }
-                write!(f, "")
+    // Like busybox, act as `svc` or `sv` when installed or linked under that
+    // name.
+    let prog = args.first()
+        .and_then(|a| Path::new(a).file_name())
+        .and_then(|n| n.to_str())
+        .unwrap_or("");
+    match prog {
+        "svc" => process::exit(cli::svc::main(&args[1..])),
+        "sv" => process::exit(cli::sv::main(&args[1..])),
+        _ => {}
+    }
+    match args.get(1).map(String::as_str) {
+        Some("svc") => process::exit(cli::svc::main(&args[2..])),
+        Some("sv") => process::exit(cli::sv::main(&args[2..])),
+        Some("bulk") => process::exit(cli::bulk::main(&args[2..])),
+        Some("reconcile") => process::exit(cli::reconcile::main(&args[2..])),
+        Some("silence") => process::exit(cli::silence::main(&args[2..])),
+        _ => {}
+    }
 
-            }
-            Some(SvstatType::SvError(e)) => write!(f, "{}: {:?}", self.name.display(), e),
-            _ => write!(f, "error with service"),
+    let (opts, dirs) = match parse_watch_args(&args[1..]) {
+        Some(parsed) => parsed,
+        None => {
+            eprintln!("usage: rupervise [-l restarts/seconds] [-H hook] [-T hook-timeout] \
+                       [-W url] [-S spool] [-f silences] service ...");
+            process::exit(100);
         }
-    }
+    };
+    watch(dirs, opts);
 }
 
+struct WatchOptions {
+    threshold: CrashLoopThreshold,
+    hooks: Hooks,
+    webhooks: Vec<Url>,
+    spool: PathBuf,
+    silences: PathBuf,
+}
 
-fn main() {
-
-    let current_path = Path::new(".");
-    let original_dir = match current_path.canonicalize() {
-        Err(why) => panic!("couldn't open current directory: {}", why.description()),
-        Ok(original_dir) => original_dir,
+fn parse_watch_args(args: &[String]) -> Option<(WatchOptions, &[String])> {
+    let mut opts = WatchOptions {
+        threshold: CrashLoopThreshold::default(),
+        hooks: Hooks::default(),
+        webhooks: Vec::new(),
+        spool: PathBuf::from(webhook::DEFAULT_SPOOL),
+        silences: silence::default_path(),
     };
-
-    let args: Vec<String> = env::args().collect();
-    let mut services: Vec<Service> = Vec::new();
-    let dirs = args.iter().skip(1);
-    for dir in dirs {
-
-        let mut service = Service {
-            name: PathBuf::from(dir),
-            status: None,
-        };
-        update_supervise(&mut service);
-        services.push(service);
-        env::set_current_dir(&original_dir).unwrap();
-
-    }
-
-    loop {
-        std::thread::sleep(std::time::Duration::from_millis(1000));
-        for sv in &mut services {
-            update_supervise(sv);
-            println!("{}", sv);
-            env::set_current_dir(&original_dir).unwrap();
+    let mut i = 0;
+    while i < args.len() && args[i].starts_with('-') {
+        let value = args.get(i + 1)?;
+        match args[i].as_str() {
+            "-l" => opts.threshold = parse_threshold(value)?,
+            "-H" => opts.hooks.global = Some(PathBuf::from(value)),
+            "-T" => opts.hooks.timeout = Duration::from_secs(value.parse().ok()?),
+            "-W" => opts.webhooks.push(value.parse().ok()?),
+            "-S" => opts.spool = PathBuf::from(value),
+            "-f" => opts.silences = PathBuf::from(value),
+            _ => return None,
         }
-        // println!("{}\n\n", services);
-        println!("");
+        i += 2;
     }
-    // println!("{:?}", services);
+    Some((opts, &args[i..]))
 }
-fn open_write<P: AsRef<Path>>(path: P) -> io::Result<File> {
-    OpenOptions::new()
-        .write(true)
-        .custom_flags(libc::O_NONBLOCK)
-        .open(path)
+
+/// Parses a crash-loop threshold given as `RESTARTS/SECONDS`.
+fn parse_threshold(s: &str) -> Option<CrashLoopThreshold> {
+    let mut parts = s.splitn(2, '/');
+    let restarts = parts.next()?.parse().ok()?;
+    let secs = parts.next()?.parse().ok()?;
+    Some(CrashLoopThreshold {
+        restarts,
+        within: Duration::from_secs(secs),
+    })
 }
 
-fn update_supervise(service: &mut Service) -> &mut Service {
-    // let mut service = service;
-    if let Err(_) = env::set_current_dir(&service.name) {
-        service.status = Some(SvstatType::SvError(SvstatError::UnableToChDir));
-        return service;
+fn watch(dirs: &[String], opts: WatchOptions) {
+    let services = dirs.iter()
+        .map(|dir| {
+            let mut service = Service::new(PathBuf::from(dir));
+            service.history = RestartHistory::new(opts.threshold);
+            service
+        })
+        .collect();
+
+    let mut watcher = Watcher::new(services);
+    let mut silences = load_silences(&opts.silences, Silences::default());
+    for sv in watcher.services() {
+        print_service(sv, &silences);
     }
-
-    let mut normally_up = false;
-    if let Err(e) = std::fs::metadata("down") {
-        if e.kind() == io::ErrorKind::NotFound {
-            normally_up = true;
-        } else {
-            service.status = Some(SvstatType::SvError(SvstatError::UnableToStatDown));
-            return service;
+    println!();
+
+    let webhook = if opts.webhooks.is_empty() {
+        None
+    } else {
+        match Webhook::new(opts.webhooks, &opts.spool) {
+            Ok(webhook) => {
+                let (errors, failed) = mpsc::channel();
+                thread::spawn(move || {
+                    for e in failed {
+                        eprintln!("rupervise: warning: {}", e);
+                    }
+                });
+                Some(webhook.spawn(errors))
+            }
+            Err(e) => {
+                eprintln!("rupervise: fatal: unable to create {}: {}", opts.spool.display(), e);
+                process::exit(111);
+            }
         }
-    }
-
-    if let Err(e) = open_write("supervise/ok") {
-        if e.kind() == io::ErrorKind::Other {
-            service.status = Some(SvstatType::SvError(SvstatError::SuperviseNotRunning));
-            return service;
+    };
+    let (runs, finished) = mpsc::channel();
+    thread::spawn(move || {
+        for run in finished.iter().filter(|run: &HookRun| !run.is_success()) {
+            eprintln!("rupervise: warning: hook {}", run);
         }
-        service.status = Some(SvstatType::SvError(SvstatError::UnableToOpenSuperviseOk));
-        return service;
-    }
-
-    let mut status_buf: [u8; 18] = [0; 18];
-    {
-        let mut status_file = match File::open("supervise/status") {
-            Ok(status_file) => status_file,
-            Err(_) => {
-                service.status =
-                    Some(SvstatType::SvError(SvstatError::UnableToOpenSuperviseStatus));
-                return service;
+    });
+    let hooks = opts.hooks.spawn(runs);
+    loop {
+        let events = watcher.next_events();
+        silences = load_silences(&opts.silences, silences);
+        for event in events {
+            print_service(&watcher.services()[event.index], &silences);
+            if silences.find(&event.service, SystemTime::now()).is_some() {
+                // Logged and counted, but nobody is told.
+                continue;
             }
-
-        };
-        let read_bytes = status_file.read(&mut status_buf[..]);
-        match read_bytes {
-            Ok(n) if n == status_buf.len() => {}
-            Ok(_) => {
-                service.status = Some(SvstatType::SvError(SvstatError::StatusBadFormat));
-                return service;
-            } 
-            Err(_) => {
-                service.status = Some(SvstatType::SvError(SvstatError::StatusOtherError));
-                return service;
+            if let Some(ref webhook) = webhook {
+                let _ = webhook.send(Notification::new(&event, &watcher.services()[event.index]));
             }
-        };
+            let _ = hooks.send(event);
+        }
+        println!();
     }
-
-    let pid = get_pid(&status_buf[12..16]);
-
-    let want = status_buf[17] as char;
-    let paused = status_buf[16] as char;
-
-    let mut when = rupervise::tai::unpack(&status_buf[0..8]);
-    let now = rupervise::tai::now();
-
-    if now < when { when = now; }
-
-
-    service.status = Some(SvstatType::SvOk {
-        pid: if pid != 0 { Some(pid) } else { None },
-        normally_up: normally_up,
-        is_paused: if paused as u8 != 0 { true } else { false },
-        duration: now.as_secs() - when.as_secs(),
-        wants: match want { 
-            'u' => Some(SvWants::WantsUp),
-            'd' => Some(SvWants::WantsDown),
-            _ => None,
-        },
-    });
-
-    service
 }
 
-fn get_pid(pid_slice: &[u8]) -> u32 {
-
-    let mut pid: u32 = pid_slice[3] as u32;
-    pid = pid << 8;
-    pid += pid_slice[2] as u32;
-    pid = pid << 8;
-    pid += pid_slice[1] as u32;
-    pid = pid << 8;
-    pid += pid_slice[0] as u32;
-
-    pid
+/// Re-reads the silences in `path`, keeping `current` if they cannot be
+/// read, so a bad edit does not lift every silence.
+fn load_silences(path: &Path, current: Silences) -> Silences {
+    match Silences::load(path) {
+        Ok(silences) => silences,
+        Err(e) => {
+            eprintln!("rupervise: warning: {}: {}", path.display(), e);
+            current
+        }
+    }
+}
 
+fn print_service(sv: &Service, silences: &Silences) {
+    match silences.find(&sv.name, SystemTime::now()) {
+        Some(silence) => println!("{}, {}", sv, silence),
+        None => println!("{}", sv),
+    }
 }
@@ -0,0 +1,265 @@
+//! Converging services on a desired state read from a file.
+//!
+//! The file has one service per line: the service directory, then `up`,
+//! `down` or `absent`. Blank lines and lines starting with `#` are ignored.
+//!
+//! ```text
+//! # web tier
+//! /service/nginx    up
+//! /service/cron     down
+//! /service/old-api  absent
+//! ```
+//!
+//! An absent service is one with no supervisor: reconciling stops it and
+//! tells its supervisor to exit. Taking the directory out of `svscan`'s
+//! scan directory, so it is not started again, is left to the caller.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use control::{self, Command};
+use status::{update_supervise, ErrorKind, Service, SvWants, SvstatError, SvstatType};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Desired {
+    Up,
+    Down,
+    Absent,
+}
+
+impl fmt::Display for Desired {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Desired::Up => write!(f, "up"),
+            Desired::Down => write!(f, "down"),
+            Desired::Absent => write!(f, "absent"),
+        }
+    }
+}
+
+/// A line of a desired-state file that could not be understood.
+#[derive(Debug)]
+pub enum ParseError {
+    Io(io::Error),
+    /// The line, counting from 1, does not have a directory and a state.
+    BadLine(usize),
+    /// The line names a state other than `up`, `down` or `absent`.
+    BadState(usize, String),
+    /// The service is listed again on this line.
+    Duplicate(usize, PathBuf),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Io(ref e) => write!(f, "unable to read desired state: {}", e),
+            ParseError::BadLine(line) => {
+                write!(f, "line {}: expected a service directory and a state", line)
+            }
+            ParseError::BadState(line, ref state) => {
+                write!(f, "line {}: unknown state {:?}, expected up, down or absent", line, state)
+            }
+            ParseError::Duplicate(line, ref path) => {
+                write!(f, "line {}: {} listed twice", line, path.display())
+            }
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ParseError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The desired state of each listed service, in file order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DesiredState {
+    pub services: Vec<(PathBuf, Desired)>,
+}
+
+impl DesiredState {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<DesiredState, ParseError> {
+        let text = fs::read_to_string(path).map_err(ParseError::Io)?;
+        DesiredState::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<DesiredState, ParseError> {
+        let mut services: Vec<(PathBuf, Desired)> = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 2 {
+                return Err(ParseError::BadLine(line_no));
+            }
+            let desired = match fields[1] {
+                "up" => Desired::Up,
+                "down" => Desired::Down,
+                "absent" => Desired::Absent,
+                other => return Err(ParseError::BadState(line_no, other.to_string())),
+            };
+            let path = PathBuf::from(fields[0]);
+            if services.iter().any(|(p, _)| *p == path) {
+                return Err(ParseError::Duplicate(line_no, path));
+            }
+            services.push((path, desired));
+        }
+        Ok(DesiredState { services })
+    }
+}
+
+/// What reconciling one service takes.
+#[derive(Debug)]
+pub struct Step {
+    pub service: Service,
+    pub desired: Desired,
+    /// The commands to send; empty if the service is already as desired.
+    /// An error if the service cannot be brought there.
+    pub commands: Result<Vec<Command>, SvstatError>,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.service.name.display())?;
+        match self.service.status {
+            Some(SvstatType::SvOk(ref st)) if st.is_up() => write!(f, "up")?,
+            Some(SvstatType::SvOk(_)) => write!(f, "down")?,
+            Some(SvstatType::SvError(ref e)) if e.kind() == ErrorKind::SuperviseNotRunning => {
+                write!(f, "not supervised")?
+            }
+            _ => write!(f, "unknown")?,
+        }
+        write!(f, " -> {}: ", self.desired)?;
+        match self.commands {
+            Ok(ref commands) if commands.is_empty() => write!(f, "nothing to do"),
+            Ok(ref commands) => {
+                let letters: String = commands.iter().map(|c| c.as_byte() as char).collect();
+                write!(f, "svc -{}", letters)
+            }
+            Err(ref e) => write!(f, "cannot reconcile: {}", e),
+        }
+    }
+}
+
+/// Works out, from the live status of each service, the fewest commands
+/// that bring it to the desired state. Nothing is sent.
+pub fn plan(desired: &DesiredState) -> Vec<Step> {
+    desired.services
+        .iter()
+        .map(|&(ref path, desired)| {
+            let mut service = Service::new(path.clone());
+            update_supervise(&mut service);
+            let commands = commands_for(desired, service.status.as_ref().expect("status was read"));
+            Step {
+                service,
+                desired,
+                commands,
+            }
+        })
+        .collect()
+}
+
+/// Sends the commands of every step that has any. Returns the steps that
+/// were acted on, with the result of sending.
+pub fn apply(steps: &[Step]) -> Vec<(&Step, Result<(), SvstatError>)> {
+    steps.iter()
+        .filter_map(|step| match step.commands {
+            Ok(ref commands) if !commands.is_empty() => {
+                Some((step, control::send(&step.service.name, commands)))
+            }
+            Ok(_) => None,
+            Err(ref e) => Some((step, Err(e.clone()))),
+        })
+        .collect()
+}
+
+fn commands_for(desired: Desired, status: &SvstatType) -> Result<Vec<Command>, SvstatError> {
+    let st = match *status {
+        SvstatType::SvOk(ref st) => st,
+        SvstatType::SvError(ref e) => {
+            // A service whose directory is gone is as absent as it gets.
+            let gone = e.kind() == ErrorKind::SuperviseNotRunning ||
+                       (e.kind() == ErrorKind::UnableToOpenDir &&
+                        e.io_error().map(io::Error::kind) == Some(io::ErrorKind::NotFound));
+            return if desired == Desired::Absent && gone {
+                Ok(Vec::new())
+            } else {
+                Err(e.clone())
+            };
+        }
+    };
+    let running = st.is_up() || st.wants == Some(SvWants::WantsUp);
+
+    let mut commands = Vec::new();
+    match desired {
+        Desired::Up => {
+            if !st.is_up() || st.wants != Some(SvWants::WantsUp) {
+                commands.push(Command::Up);
+            }
+            if st.is_paused {
+                commands.push(Command::Continue);
+            }
+        }
+        Desired::Down => {
+            if running {
+                commands.push(Command::Down);
+            }
+        }
+        Desired::Absent => {
+            if running {
+                commands.push(Command::Down);
+            }
+            commands.push(Command::Exit);
+        }
+    }
+    Ok(commands)
+}
+
+#[test]
+fn parse_desired_state() {
+    let state = DesiredState::parse("# comment\n\n/service/a up\n  /service/b\tabsent  \n").unwrap();
+    assert_eq!(vec![(PathBuf::from("/service/a"), Desired::Up),
+                    (PathBuf::from("/service/b"), Desired::Absent)],
+               state.services);
+
+    match DesiredState::parse("/service/a up\n/service/b sideways\n") {
+        Err(ParseError::BadState(2, ref s)) if s == "sideways" => {}
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(DesiredState::parse("/service/a\n").is_err());
+    assert!(DesiredState::parse("/service/a up\n/service/a down\n").is_err());
+}
+
+#[test]
+fn minimal_commands() {
+    use status::ServiceStatus;
+    use testing;
+
+    let status = |up: bool, wants| {
+        let pid = if up { Some(10) } else { None };
+        SvstatType::SvOk(ServiceStatus { wants: Some(wants), ..testing::status(pid) })
+    };
+    let up = status(true, SvWants::WantsUp);
+    let down = status(false, SvWants::WantsDown);
+    let gone = SvstatType::SvError(SvstatError::new(ErrorKind::SuperviseNotRunning, "/service/a"));
+
+    assert_eq!(Ok(vec![]), commands_for(Desired::Up, &up));
+    assert_eq!(Ok(vec![Command::Up]), commands_for(Desired::Up, &down));
+    assert_eq!(Ok(vec![Command::Up]), commands_for(Desired::Up, &status(true, SvWants::WantsDown)));
+    assert_eq!(Ok(vec![Command::Down]), commands_for(Desired::Down, &up));
+    assert_eq!(Ok(vec![]), commands_for(Desired::Down, &down));
+    assert_eq!(Ok(vec![Command::Down, Command::Exit]), commands_for(Desired::Absent, &up));
+    assert_eq!(Ok(vec![Command::Exit]), commands_for(Desired::Absent, &down));
+    assert_eq!(Ok(vec![]), commands_for(Desired::Absent, &gone));
+    assert!(commands_for(Desired::Up, &gone).is_err());
+}
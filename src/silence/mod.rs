@@ -0,0 +1,365 @@
+//! Silencing notifications for services under planned maintenance.
+//!
+//! A silence matches services by a glob, lasts until a given time and
+//! records who set it and why. Silenced services are still watched and
+//! logged, but their hooks and webhooks are not run. Silences live in a
+//! file, one per line: the pattern, the end as seconds since the epoch,
+//! who set it, then the reason.
+//!
+//! ```text
+//! /service/db-*  1792332000  alice  db migration
+//! nginx          1792328400  bob    certificate rollover
+//! ```
+//!
+//! A pattern with a `/` is matched against the whole service path, one
+//! without against its last component.
+//!
+//! Anyone who can write the file can silence every alert, so it is only
+//! trusted if it belongs to us or to root and nobody else may write to it.
+
+use std::error::Error;
+use std::ffi::CString;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use libc;
+
+/// Where silences are kept unless told otherwise.
+pub fn default_path() -> PathBuf {
+    PathBuf::from("/var/lib/rupervise/silences")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Silence {
+    pub pattern: String,
+    pub until: SystemTime,
+    pub by: String,
+    pub reason: String,
+}
+
+impl Silence {
+    /// Whether the silence can be written to a silences file and read back:
+    /// the pattern and name are single fields that do not start a comment,
+    /// and nothing holds a control character that could end the line.
+    pub fn is_valid(&self) -> bool {
+        let field = |s: &str| {
+            !s.is_empty() && !s.contains(|c: char| c.is_whitespace() || c.is_control())
+        };
+        field(&self.pattern) && !self.pattern.starts_with('#') && field(&self.by) &&
+        !self.reason.contains(char::is_control)
+    }
+
+    pub fn is_active(&self, now: SystemTime) -> bool {
+        now < self.until
+    }
+
+    pub fn matches(&self, service: &Path) -> bool {
+        let name = if self.pattern.contains('/') {
+            service.as_os_str()
+        } else {
+            match service.file_name() {
+                Some(name) => name,
+                None => return false,
+            }
+        };
+        let (pattern, name) = match (CString::new(self.pattern.as_bytes()),
+                                     CString::new(name.as_bytes())) {
+            (Ok(pattern), Ok(name)) => (pattern, name),
+            _ => return false,
+        };
+        unsafe { libc::fnmatch(pattern.as_ptr(), name.as_ptr(), 0) == 0 }
+    }
+}
+
+impl fmt::Display for Silence {
+    /// Formats the silence as it is shown after a service's status, such as
+    /// `silenced by alice: db migration`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "silenced by {}", self.by)?;
+        if !self.reason.is_empty() {
+            write!(f, ": {}", self.reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// A line of a silences file that could not be understood.
+#[derive(Debug)]
+pub enum ParseError {
+    Io(io::Error),
+    /// The line, counting from 1, lacks a pattern, an end or a name.
+    BadLine(usize),
+    /// The end on the line is not a number of seconds.
+    BadTime(usize, String),
+    /// The file belongs to someone other than us or root, or others may
+    /// write to it.
+    Untrusted,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Io(ref e) => write!(f, "unable to read silences: {}", e),
+            ParseError::BadLine(line) => {
+                write!(f, "line {}: expected a pattern, an end time and a name", line)
+            }
+            ParseError::BadTime(line, ref time) => {
+                write!(f, "line {}: bad end time {:?}, expected seconds since the epoch",
+                       line,
+                       time)
+            }
+            ParseError::Untrusted => {
+                write!(f, "not trusted: owned by another user or writable by others")
+            }
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ParseError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Every silence in a file, in the order they were set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Silences {
+    pub silences: Vec<Silence>,
+}
+
+impl Silences {
+    /// Reads the silences in `path`. A file that does not exist holds none.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Silences, ParseError> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Silences::default()),
+            Err(e) => return Err(ParseError::Io(e)),
+        };
+        let meta = file.metadata().map_err(ParseError::Io)?;
+        let owner = meta.uid();
+        if (owner != 0 && owner != unsafe { libc::geteuid() }) || meta.mode() & 0o022 != 0 {
+            return Err(ParseError::Untrusted);
+        }
+        let mut text = String::new();
+        file.read_to_string(&mut text).map_err(ParseError::Io)?;
+        Silences::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Silences, ParseError> {
+        let mut silences = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.splitn(4, char::is_whitespace).filter(|f| !f.is_empty());
+            let (pattern, until, by) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(pattern), Some(until), Some(by)) => (pattern, until, by),
+                _ => return Err(ParseError::BadLine(line_no)),
+            };
+            let secs = until.parse().map_err(|_| ParseError::BadTime(line_no, until.to_string()))?;
+            silences.push(Silence {
+                pattern: pattern.to_string(),
+                until: UNIX_EPOCH + Duration::from_secs(secs),
+                by: by.to_string(),
+                reason: fields.next().unwrap_or("").trim().to_string(),
+            });
+        }
+        Ok(Silences { silences })
+    }
+
+    /// Writes the silences to `path`, replacing it in one step, and creates
+    /// its directory if need be. Only we may write either. Fails without
+    /// writing anything if a silence is not valid.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        if let Some(s) = self.silences.iter().find(|s| !s.is_valid()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("silence {:?} cannot be saved", s.pattern)));
+        }
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::DirBuilder::new().recursive(true).mode(0o755).create(dir)?;
+        }
+        let mut text = String::new();
+        for s in &self.silences {
+            let secs = s.until.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+            text.push_str(&format!("{}\t{}\t{}\t{}\n", s.pattern, secs, s.by, s.reason));
+        }
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".new");
+        let _ = fs::remove_file(&tmp);
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o644)
+            .open(&tmp)?
+            .write_all(text.as_bytes())?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Adds `silence`, replacing any other with the same pattern.
+    pub fn add(&mut self, silence: Silence) {
+        self.remove(&silence.pattern);
+        self.silences.push(silence);
+    }
+
+    /// Lifts the silence set with `pattern`. Returns whether there was one.
+    pub fn remove(&mut self, pattern: &str) -> bool {
+        let before = self.silences.len();
+        self.silences.retain(|s| s.pattern != pattern);
+        self.silences.len() != before
+    }
+
+    /// Forgets silences that have ended.
+    pub fn prune(&mut self, now: SystemTime) {
+        self.silences.retain(|s| s.is_active(now));
+    }
+
+    /// The silence in force for `service`, if any. When several match, the
+    /// one set last wins.
+    pub fn find(&self, service: &Path, now: SystemTime) -> Option<&Silence> {
+        self.silences.iter().rev().find(|s| s.is_active(now) && s.matches(service))
+    }
+}
+
+/// Parses a time given as `@SECONDS` since the epoch, or in local time as
+/// `YYYY-MM-DD HH:MM[:SS]` (a `T` may stand in for the space).
+pub fn parse_time(s: &str) -> Option<SystemTime> {
+    if let Some(secs) = s.strip_prefix('@') {
+        return secs.parse().ok().map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+    }
+    let text = CString::new(s).ok()?;
+    for format in &["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"] {
+        let format = CString::new(*format).unwrap();
+        let mut tm: libc::tm = unsafe { mem::zeroed() };
+        let end = unsafe { libc::strptime(text.as_ptr(), format.as_ptr(), &mut tm) };
+        if end.is_null() || unsafe { *end } != 0 {
+            continue;
+        }
+        tm.tm_isdst = -1;
+        let secs = unsafe { libc::mktime(&mut tm) };
+        return if secs < 0 {
+            None
+        } else {
+            Some(UNIX_EPOCH + Duration::from_secs(secs as u64))
+        };
+    }
+    None
+}
+
+/// Formats `time` in local time as `YYYY-MM-DD HH:MM`.
+pub fn local_time(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as libc::time_t;
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    let mut buf = [0u8; 32];
+    let len = unsafe {
+        libc::localtime_r(&secs, &mut tm);
+        libc::strftime(buf.as_mut_ptr() as *mut libc::c_char,
+                       buf.len(),
+                       b"%Y-%m-%d %H:%M\0".as_ptr() as *const libc::c_char,
+                       &tm)
+    };
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+#[test]
+fn parse_and_match() {
+    let silences = Silences::parse("# maintenance\n\
+                                    /service/db-*\t1000\talice\tdb migration\n\
+                                    nginx 3000 bob\n")
+        .unwrap();
+    assert_eq!(2, silences.silences.len());
+    assert_eq!("", silences.silences[1].reason);
+
+    let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+    let db = Path::new("/service/db-main");
+    assert_eq!("silenced by alice: db migration",
+               silences.find(db, at(999)).unwrap().to_string());
+    assert_eq!(None, silences.find(db, at(1000)));
+    assert_eq!(None, silences.find(Path::new("/other/db-main"), at(0)));
+    assert_eq!("silenced by bob",
+               silences.find(Path::new("/etc/sv/nginx"), at(0)).unwrap().to_string());
+
+    assert!(Silences::parse("nginx 3000\n").is_err());
+    assert!(Silences::parse("nginx soon bob\n").is_err());
+}
+
+#[test]
+fn save_and_load() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = ::std::env::temp_dir().join(format!("rupervise-silences-{}", ::std::process::id()));
+    assert_eq!(Silences::default(), Silences::load(&path).unwrap());
+
+    let mut silences = Silences::default();
+    silences.add(Silence {
+        pattern: "db-*".to_string(),
+        until: UNIX_EPOCH + Duration::from_secs(2000),
+        by: "alice".to_string(),
+        reason: "db migration".to_string(),
+    });
+    silences.save(&path).unwrap();
+    assert_eq!(silences, Silences::load(&path).unwrap());
+
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o666)).unwrap();
+    match Silences::load(&path) {
+        Err(ParseError::Untrusted) => {}
+        other => panic!("loaded a file anyone can write: {:?}", other),
+    }
+
+    assert!(silences.remove("db-*"));
+    assert!(!silences.remove("db-*"));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn save_refuses_what_would_not_load() {
+    let path = ::std::env::temp_dir().join(format!("rupervise-silences-bad-{}",
+                                                   ::std::process::id()));
+    let silence = |pattern: &str, reason: &str| {
+        Silence {
+            pattern: pattern.to_string(),
+            until: UNIX_EPOCH + Duration::from_secs(2000),
+            by: "alice".to_string(),
+            reason: reason.to_string(),
+        }
+    };
+
+    let mut silences = Silences::default();
+    silences.add(silence("/service/db-[0-9]*", "db  migration, caf\u{e9} closed"));
+    silences.save(&path).unwrap();
+    assert_eq!(silences, Silences::load(&path).unwrap());
+
+    for bad in &[silence("db *", ""),
+                 silence("#db", ""),
+                 silence("db-*", "migration\nstep two"),
+                 silence("db-*", "migration\tstep two")] {
+        assert!(!bad.is_valid());
+        let mut more = silences.clone();
+        more.add(bad.clone());
+        assert_eq!(io::ErrorKind::InvalidInput, more.save(&path).unwrap_err().kind());
+    }
+    assert_eq!(silences, Silences::load(&path).unwrap());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn parse_times() {
+    assert_eq!(Some(UNIX_EPOCH + Duration::from_secs(1700000000)), parse_time("@1700000000"));
+    let local = parse_time("2026-10-18 14:30").unwrap();
+    assert_eq!(Some(local), parse_time("2026-10-18T14:30:00"));
+    assert_eq!("2026-10-18 14:30", local_time(local));
+    assert_eq!(None, parse_time("tomorrow"));
+    assert_eq!(None, parse_time("2026-10-18 14:30 extra"));
+}
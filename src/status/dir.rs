@@ -0,0 +1,97 @@
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+
+use libc;
+
+/// An open handle on a service directory.
+///
+/// Files inside the directory are opened with `openat(2)` relative to the
+/// handle, so reads neither depend on nor change the working directory of
+/// the process and may run concurrently from several threads.
+#[derive(Debug)]
+pub struct ServiceDir {
+    dir: File,
+    path: PathBuf,
+}
+
+impl ServiceDir {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<ServiceDir> {
+        let dir = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_DIRECTORY | libc::O_CLOEXEC)
+            .open(path.as_ref())?;
+        Ok(ServiceDir {
+            dir,
+            path: path.as_ref().to_path_buf(),
+        })
+    }
+
+    /// The path the directory was opened with.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Opens `name`, relative to the service directory, with the raw
+    /// `open(2)` `flags`.
+    pub fn open_at<P: AsRef<Path>>(&self, name: P, flags: libc::c_int) -> io::Result<File> {
+        let name = cstring(name.as_ref())?;
+        let fd = unsafe {
+            libc::openat(self.dir.as_raw_fd(), name.as_ptr(), flags | libc::O_CLOEXEC, 0o644)
+        };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    pub fn open_read<P: AsRef<Path>>(&self, name: P) -> io::Result<File> {
+        self.open_at(name, libc::O_RDONLY)
+    }
+
+    /// Opens `name` for writing without blocking, the way svc and svstat
+    /// probe the `supervise/ok` and `supervise/control` FIFOs.
+    pub fn open_write<P: AsRef<Path>>(&self, name: P) -> io::Result<File> {
+        self.open_at(name, libc::O_WRONLY | libc::O_NONBLOCK)
+    }
+
+    /// Returns `Ok(false)` if `name` does not exist, the way `stat(2)` on the
+    /// `down` file is used to decide whether a service is normally up.
+    pub fn exists<P: AsRef<Path>>(&self, name: P) -> io::Result<bool> {
+        let name = cstring(name.as_ref())?;
+        let mut st: libc::stat = unsafe { mem::zeroed() };
+        let r = unsafe { libc::fstatat(self.dir.as_raw_fd(), name.as_ptr(), &mut st, 0) };
+        if r == -1 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::NotFound {
+                return Ok(false);
+            }
+            return Err(e);
+        }
+        Ok(true)
+    }
+}
+
+fn cstring(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains a nul byte"))
+}
+
+#[test]
+fn exists_relative_to_handle() {
+    let path = ::std::env::temp_dir().join(format!("rupervise-dir-{}", ::std::process::id()));
+    ::std::fs::create_dir_all(&path).unwrap();
+    File::create(path.join("down")).unwrap();
+
+    let dir = ServiceDir::open(&path).unwrap();
+    assert!(dir.exists("down").unwrap());
+    assert!(!dir.exists("run").unwrap());
+    assert!(dir.open_read("down").is_ok());
+
+    ::std::fs::remove_dir_all(&path).unwrap();
+}
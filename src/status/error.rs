@@ -0,0 +1,169 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{Liveness, NotRunning};
+
+/// What went wrong while reading or controlling a service.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ErrorKind {
+    UnableToOpenDir,
+    UnableToStatDown,
+    SuperviseNotRunning,
+    UnableToOpenSuperviseOk,
+    UnableToOpenSuperviseStatus,
+    StatusBadFormat,
+    StatusOtherError,
+    UnableToOpenSuperviseControl,
+    UnableToWriteSuperviseControl,
+}
+
+/// An error reading or controlling a service directory, with the directory
+/// it happened in and, where there was one, the underlying I/O error.
+#[derive(Debug)]
+pub struct SvstatError {
+    kind: ErrorKind,
+    path: PathBuf,
+    source: Option<io::Error>,
+    not_running: Option<NotRunning>,
+}
+
+impl SvstatError {
+    pub fn new<P: Into<PathBuf>>(kind: ErrorKind, path: P) -> SvstatError {
+        SvstatError {
+            kind,
+            path: path.into(),
+            source: None,
+            not_running: None,
+        }
+    }
+
+    pub fn with_source<P: Into<PathBuf>>(kind: ErrorKind, path: P, source: io::Error) -> SvstatError {
+        SvstatError {
+            kind,
+            path: path.into(),
+            source: Some(source),
+            not_running: None,
+        }
+    }
+
+    /// A `SuperviseNotRunning` error, with the reason it was found not to be.
+    pub fn not_running<P: Into<PathBuf>>(path: P,
+                                         reason: NotRunning,
+                                         source: Option<io::Error>)
+                                         -> SvstatError {
+        SvstatError {
+            kind: ErrorKind::SuperviseNotRunning,
+            path: path.into(),
+            source,
+            not_running: Some(reason),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The service directory the error happened in.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn io_error(&self) -> Option<&io::Error> {
+        self.source.as_ref()
+    }
+
+    /// What the error says about the supervisor: errors from reading the
+    /// status file mean it was running, errors from before it could be
+    /// checked leave it unknown.
+    pub fn liveness(&self) -> Liveness {
+        match self.kind {
+            ErrorKind::SuperviseNotRunning => {
+                Liveness::NotRunning(self.not_running.unwrap_or(NotRunning::NoReader))
+            }
+            ErrorKind::UnableToOpenSuperviseStatus |
+            ErrorKind::StatusBadFormat |
+            ErrorKind::StatusOtherError |
+            ErrorKind::UnableToWriteSuperviseControl => Liveness::Running,
+            ErrorKind::UnableToOpenDir |
+            ErrorKind::UnableToStatDown |
+            ErrorKind::UnableToOpenSuperviseOk |
+            ErrorKind::UnableToOpenSuperviseControl => Liveness::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for SvstatError {
+    /// Formats the error the way `svstat` reports it, prefixed with the
+    /// service directory.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.path.display())?;
+        let msg = match self.kind {
+            ErrorKind::UnableToOpenDir => "unable to open directory",
+            ErrorKind::UnableToStatDown => "unable to stat down",
+            ErrorKind::SuperviseNotRunning => "supervise not running",
+            ErrorKind::UnableToOpenSuperviseOk => "unable to open supervise/ok",
+            ErrorKind::UnableToOpenSuperviseStatus => "unable to open supervise/status",
+            ErrorKind::StatusBadFormat => "unable to read supervise/status: bad format",
+            ErrorKind::StatusOtherError => "unable to read supervise/status",
+            ErrorKind::UnableToOpenSuperviseControl => "unable to open supervise/control",
+            ErrorKind::UnableToWriteSuperviseControl => "error writing commands to supervise/control",
+        };
+        match self.source {
+            Some(ref e) if self.kind != ErrorKind::SuperviseNotRunning => {
+                write!(f, "{}: {}", msg, e)
+            }
+            _ => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Error for SvstatError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_ref().map(|e| e as &(dyn Error + 'static))
+    }
+}
+
+impl Clone for SvstatError {
+    fn clone(&self) -> SvstatError {
+        SvstatError {
+            kind: self.kind,
+            path: self.path.clone(),
+            source: self.source.as_ref().map(|e| match e.raw_os_error() {
+                Some(errno) => io::Error::from_raw_os_error(errno),
+                None => io::Error::new(e.kind(), e.to_string()),
+            }),
+            not_running: self.not_running,
+        }
+    }
+}
+
+/// Errors are equal if they are of the same kind, in the same directory,
+/// and caused by the same kind of I/O error or the same reason the
+/// supervisor was not running.
+impl PartialEq for SvstatError {
+    fn eq(&self, other: &SvstatError) -> bool {
+        self.kind == other.kind && self.path == other.path &&
+        self.not_running == other.not_running &&
+        self.source.as_ref().map(io::Error::kind) == other.source.as_ref().map(io::Error::kind)
+    }
+}
+
+#[test]
+fn display_includes_cause() {
+    let e = SvstatError::with_source(ErrorKind::UnableToOpenSuperviseOk,
+                                     "/service/foo",
+                                     io::Error::from_raw_os_error(13));
+    assert_eq!(ErrorKind::UnableToOpenSuperviseOk, e.kind());
+    assert!(e.to_string().starts_with("/service/foo: unable to open supervise/ok: "));
+    assert_eq!(Some(13), e.io_error().and_then(io::Error::raw_os_error));
+    assert_eq!(e, e.clone());
+}
+
+#[test]
+fn display_without_cause() {
+    let e = SvstatError::new(ErrorKind::StatusBadFormat, "/service/foo");
+    assert_eq!("/service/foo: unable to read supervise/status: bad format", e.to_string());
+    assert!(e.source().is_none());
+}
@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use super::{transitions, SvWants, SvstatType, Transition};
+
+const MINUTE: Duration = Duration::from_secs(60);
+const TEN_MINUTES: Duration = Duration::from_secs(600);
+const HOUR: Duration = Duration::from_secs(3600);
+
+/// How many restarts within how long make a crash loop.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CrashLoopThreshold {
+    pub restarts: usize,
+    pub within: Duration,
+}
+
+impl Default for CrashLoopThreshold {
+    fn default() -> CrashLoopThreshold {
+        CrashLoopThreshold {
+            restarts: 5,
+            within: MINUTE,
+        }
+    }
+}
+
+/// Restarts seen in the last minute, ten minutes and hour.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct RestartCounts {
+    pub last_minute: usize,
+    pub last_ten_minutes: usize,
+    pub last_hour: usize,
+}
+
+impl fmt::Display for RestartCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "restarts {}/{}/{} in 1m/10m/1h",
+               self.last_minute,
+               self.last_ten_minutes,
+               self.last_hour)
+    }
+}
+
+/// When a service was seen to restart, going back an hour, or further if
+/// the crash-loop window is longer.
+///
+/// A restart is a new pid, or a reset `duration`, while the service stays
+/// up, or the service coming back up after going down on its own. Starting
+/// a service that was wanted down is not counted.
+#[derive(Debug, Clone, Default)]
+pub struct RestartHistory {
+    restarts: VecDeque<Instant>,
+    pub threshold: CrashLoopThreshold,
+}
+
+impl RestartHistory {
+    pub fn new(threshold: CrashLoopThreshold) -> RestartHistory {
+        RestartHistory {
+            restarts: VecDeque::new(),
+            threshold,
+        }
+    }
+
+    /// Records the restarts between two snapshots, taken `now`.
+    pub fn record(&mut self, old: &SvstatType, new: &SvstatType, now: Instant) {
+        let wanted_up = match *old {
+            SvstatType::SvOk(ref st) => st.wants == Some(SvWants::WantsUp),
+            SvstatType::SvError(_) => false,
+        };
+        for t in transitions(old, new) {
+            match t {
+                Transition::Restarted { .. } => self.restarts.push_back(now),
+                Transition::Up { .. } if wanted_up => self.restarts.push_back(now),
+                _ => {}
+            }
+        }
+
+        let keep = HOUR.max(self.threshold.within);
+        while let Some(&oldest) = self.restarts.front() {
+            if now.duration_since(oldest) <= keep {
+                break;
+            }
+            self.restarts.pop_front();
+        }
+    }
+
+    /// The number of restarts in the `window` before `now`.
+    pub fn count_within(&self, window: Duration, now: Instant) -> usize {
+        self.restarts.iter().filter(|&&t| now.duration_since(t) <= window).count()
+    }
+
+    pub fn counts(&self, now: Instant) -> RestartCounts {
+        RestartCounts {
+            last_minute: self.count_within(MINUTE, now),
+            last_ten_minutes: self.count_within(TEN_MINUTES, now),
+            last_hour: self.count_within(HOUR, now),
+        }
+    }
+
+    pub fn is_crash_looping(&self, now: Instant) -> bool {
+        self.threshold.restarts > 0 &&
+        self.count_within(self.threshold.within, now) >= self.threshold.restarts
+    }
+}
+
+#[test]
+fn counts_restarts_and_flags_crash_loop() {
+    use super::ServiceStatus;
+    use testing;
+
+    let status = |pid, wants| {
+        SvstatType::SvOk(ServiceStatus { wants: Some(wants), ..testing::status(pid) })
+    };
+
+    let mut history = RestartHistory::new(CrashLoopThreshold {
+        restarts: 3,
+        within: MINUTE,
+    });
+    let start = Instant::now();
+    // Started by hand: not a restart.
+    history.record(&status(None, SvWants::WantsDown), &status(Some(10), SvWants::WantsUp), start);
+    assert_eq!(0, history.count_within(HOUR, start));
+
+    let at = |secs| start + Duration::from_secs(secs);
+    history.record(&status(Some(10), SvWants::WantsUp), &status(Some(11), SvWants::WantsUp), at(1));
+    history.record(&status(None, SvWants::WantsUp), &status(Some(12), SvWants::WantsUp), at(120));
+    history.record(&status(Some(12), SvWants::WantsUp), &status(Some(13), SvWants::WantsUp), at(130));
+    assert_eq!(RestartCounts {
+                   last_minute: 2,
+                   last_ten_minutes: 3,
+                   last_hour: 3,
+               },
+               history.counts(at(140)));
+    assert!(!history.is_crash_looping(at(140)));
+
+    history.record(&status(Some(13), SvWants::WantsUp), &status(Some(14), SvWants::WantsUp), at(135));
+    assert!(history.is_crash_looping(at(140)));
+    assert_eq!(0, history.counts(at(5000)).last_hour);
+}
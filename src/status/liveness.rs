@@ -0,0 +1,127 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+
+use libc;
+
+use super::ServiceDir;
+
+/// Whether a supervisor is running for a service directory.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Liveness {
+    Running,
+    NotRunning(NotRunning),
+    /// The directory could not be checked, for example for lack of
+    /// permission.
+    Unknown,
+}
+
+/// Why a service is known to have no supervisor.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum NotRunning {
+    /// There is no `supervise/ok` FIFO (nor, for s6, `supervise/control`):
+    /// no supervisor has ever run here.
+    FifoMissing,
+    /// The FIFO has no reader, but `supervise/lock` is held or could not be
+    /// checked: the supervisor may be starting up or shutting down.
+    NoReader,
+    /// The FIFO has no reader and nothing holds `supervise/lock`: the
+    /// supervisor is gone.
+    LockNotHeld,
+}
+
+impl fmt::Display for NotRunning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NotRunning::FifoMissing => write!(f, "supervise/ok missing"),
+            NotRunning::NoReader => write!(f, "no reader on supervise/ok"),
+            NotRunning::LockNotHeld => write!(f, "supervise/lock not held"),
+        }
+    }
+}
+
+impl ServiceDir {
+    /// Checks whether a supervisor is running here, the way `svok` does,
+    /// and if not, why not.
+    pub fn liveness(&self) -> Liveness {
+        self.probe().unwrap_or(Liveness::Unknown)
+    }
+
+    /// Like `liveness`, but keeps the error that made the answer unknown.
+    pub(crate) fn probe(&self) -> io::Result<Liveness> {
+        // s6-supervise has no `ok` FIFO and listens on `control` instead.
+        let fifo = match self.open_write("supervise/ok") {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                self.open_write("supervise/control")
+            }
+            fifo => fifo,
+        };
+        match fifo {
+            Ok(_) => Ok(Liveness::Running),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                Ok(Liveness::NotRunning(NotRunning::FifoMissing))
+            }
+            Err(ref e) if e.raw_os_error() == Some(libc::ENXIO) => {
+                match self.lock_held() {
+                    Some(false) => Ok(Liveness::NotRunning(NotRunning::LockNotHeld)),
+                    _ => Ok(Liveness::NotRunning(NotRunning::NoReader)),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether anything holds a lock on `supervise/lock`, or `None` if that
+    /// cannot be told.
+    ///
+    /// Trying the lock ourselves could make a supervisor that is just
+    /// starting fail to take it, so the lock is looked up in `/proc/locks`
+    /// instead.
+    fn lock_held(&self) -> Option<bool> {
+        let lock = self.open_read("supervise/lock").ok()?.metadata().ok()?;
+        let locks = fs::read_to_string("/proc/locks").ok()?;
+        let id = format!("{:02x}:{:02x}:{}", dev_major(lock.dev()), dev_minor(lock.dev()), lock.ino());
+        Some(locks.lines().any(|line| {
+            // Waiters are listed after the holder, marked with "->".
+            !line.contains("->") && line.split_whitespace().nth(5) == Some(id.as_str())
+        }))
+    }
+}
+
+/// The major number of a device, as glibc's `major(3)` splits it.
+fn dev_major(dev: u64) -> u64 {
+    ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff)
+}
+
+fn dev_minor(dev: u64) -> u64 {
+    (dev & 0xff) | ((dev >> 12) & !0xff)
+}
+
+#[test]
+fn liveness_reasons() {
+    use std::ffi::CString;
+    use std::fs::File;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::AsRawFd;
+    use testing::open_fifo;
+
+    let path = ::std::env::temp_dir().join(format!("rupervise-liveness-{}", ::std::process::id()));
+    fs::create_dir_all(path.join("supervise")).unwrap();
+    let dir = ServiceDir::open(&path).unwrap();
+    assert_eq!(Liveness::NotRunning(NotRunning::FifoMissing), dir.liveness());
+
+    let fifo = CString::new(path.join("supervise/ok").as_os_str().as_bytes()).unwrap();
+    assert_eq!(0, unsafe { libc::mkfifo(fifo.as_ptr(), 0o600) });
+    let lock = File::create(path.join("supervise/lock")).unwrap();
+    if fs::metadata("/proc/locks").is_ok() {
+        assert_eq!(Liveness::NotRunning(NotRunning::LockNotHeld), dir.liveness());
+    }
+
+    assert_eq!(0, unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) });
+    assert_eq!(Liveness::NotRunning(NotRunning::NoReader), dir.liveness());
+
+    let _reader = open_fifo(&path, "supervise/ok");
+    assert_eq!(Liveness::Running, dir.liveness());
+    fs::remove_dir_all(&path).unwrap();
+}
@@ -0,0 +1,611 @@
+use std::fmt;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use libc;
+
+use tai;
+
+mod dir;
+mod error;
+mod history;
+mod liveness;
+mod params;
+mod readiness;
+mod transition;
+pub use self::dir::ServiceDir;
+pub use self::error::{ErrorKind, SvstatError};
+pub use self::history::{CrashLoopThreshold, RestartCounts, RestartHistory};
+pub use self::liveness::{Liveness, NotRunning};
+pub use self::params::status_params;
+pub use self::readiness::Readiness;
+pub use self::transition::{transitions, Transition};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SvWants {
+    WantsUp,
+    WantsDown,
+}
+
+/// The supervisor that wrote a `supervise/status` file, told apart by its
+/// length.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StatusFormat {
+    /// daemontools `supervise`: 18 bytes.
+    Daemontools,
+    /// runit `runsv`: 20 bytes, adding the TERM flag and the run state.
+    Runit,
+    /// s6 `s6-supervise`: 35 bytes, or 43 bytes since s6 2.10 added the
+    /// process group.
+    S6,
+}
+
+/// What the supervisor is doing with the service.
+///
+/// daemontools only knows `Run` and `Down`; runit and s6 also report `Finish`
+/// while `./finish` is running.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RunState {
+    Down,
+    Run,
+    Finish,
+}
+
+/// How the last run of the service ended, decoded from its wait status.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LastExit {
+    Code(u8),
+    Signal(u8),
+}
+
+const DAEMONTOOLS_STATUS_LEN: usize = 18;
+const RUNIT_STATUS_LEN: usize = 20;
+const S6_STATUS_LEN: usize = 35;
+const S6_PGID_STATUS_LEN: usize = 43;
+const MAX_STATUS_LEN: usize = S6_PGID_STATUS_LEN;
+
+const S6_FLAG_PAUSED: u8 = 0x01;
+const S6_FLAG_FINISHING: u8 = 0x02;
+const S6_FLAG_WANTUP: u8 = 0x04;
+const S6_FLAG_READY: u8 = 0x08;
+
+/// A decoded `supervise/status` snapshot for one service directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceStatus {
+    pub pid: Option<u32>,
+    pub normally_up: bool,
+    pub is_paused: bool,
+    pub duration: u64,
+    /// When the service last changed state, to the nanosecond.
+    pub since: tai::Tain,
+    pub wants: Option<SvWants>,
+    pub format: StatusFormat,
+    pub run_state: RunState,
+    /// runit only: a TERM has been sent and the service has not exited yet.
+    pub got_term: bool,
+    /// s6 only: how the previous run of the service ended.
+    pub last_exit: Option<LastExit>,
+    /// From s6's readiness notification, or, once `check_readiness` has
+    /// been called, from the checks configured in the service directory.
+    pub readiness: Readiness,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SvstatType {
+    SvError(SvstatError),
+    SvOk(ServiceStatus),
+}
+
+#[derive(Debug)]
+pub struct Service {
+    pub name: PathBuf,
+    pub status: Option<SvstatType>,
+    /// Restarts seen by `update_supervise`.
+    pub history: RestartHistory,
+}
+
+impl ServiceStatus {
+    /// Reads the status of the service directory at `path`.
+    ///
+    /// All files are resolved relative to an open handle on `path`; the
+    /// current working directory of the process is never changed.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<ServiceStatus, SvstatError> {
+        let path = path.as_ref();
+        match ServiceDir::open(path) {
+            Ok(dir) => ServiceStatus::read_dir(&dir),
+            Err(e) => Err(SvstatError::with_source(ErrorKind::UnableToOpenDir, path, e)),
+        }
+    }
+
+    /// Reads the status of an already opened service directory.
+    pub fn read_dir(dir: &ServiceDir) -> Result<ServiceStatus, SvstatError> {
+        let fail = |kind, e| SvstatError::with_source(kind, dir.path(), e);
+
+        let normally_up = match dir.exists("down") {
+            Ok(down) => !down,
+            Err(e) => return Err(fail(ErrorKind::UnableToStatDown, e)),
+        };
+
+        match dir.probe() {
+            Ok(Liveness::NotRunning(reason)) => {
+                return Err(SvstatError::not_running(dir.path(), reason, None));
+            }
+            Ok(_) => {}
+            Err(e) => return Err(fail(ErrorKind::UnableToOpenSuperviseOk, e)),
+        }
+
+        let status_file = match dir.open_read("supervise/status") {
+            Ok(status_file) => status_file,
+            Err(e) => return Err(fail(ErrorKind::UnableToOpenSuperviseStatus, e)),
+        };
+        let mut status_buf = Vec::with_capacity(MAX_STATUS_LEN);
+        if let Err(e) = status_file.take(MAX_STATUS_LEN as u64 + 1).read_to_end(&mut status_buf) {
+            return Err(fail(ErrorKind::StatusOtherError, e));
+        }
+
+        decode(&status_buf, normally_up, tai::now())
+            .map_err(|kind| SvstatError::new(kind, dir.path()))
+    }
+
+    /// Works out the readiness of an up service whose supervisor does not
+    /// track it, from a `ready-file` or `check` script in `dir`.
+    ///
+    /// Running `check` forks and can take seconds, so reads leave this to
+    /// callers that show readiness.
+    pub fn check_readiness(&mut self, dir: &ServiceDir) {
+        if self.is_up() && self.readiness == Readiness::Unknown {
+            self.readiness = readiness::check(dir, self);
+        }
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.run_state == RunState::Run
+    }
+}
+
+impl SvstatType {
+    pub fn liveness(&self) -> Liveness {
+        match *self {
+            SvstatType::SvOk(_) => Liveness::Running,
+            SvstatType::SvError(ref e) => e.liveness(),
+        }
+    }
+}
+
+impl From<Result<ServiceStatus, SvstatError>> for SvstatType {
+    fn from(result: Result<ServiceStatus, SvstatError>) -> SvstatType {
+        match result {
+            Ok(status) => SvstatType::SvOk(status),
+            Err(e) => SvstatType::SvError(e),
+        }
+    }
+}
+
+impl Service {
+    pub fn new<P: Into<PathBuf>>(name: P) -> Service {
+        Service {
+            name: name.into(),
+            status: None,
+            history: RestartHistory::default(),
+        }
+    }
+}
+
+impl fmt::Display for Service {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let st = match self.status {
+            Some(SvstatType::SvOk(ref st)) => st,
+            Some(SvstatType::SvError(ref e)) => return write!(f, "{}", e),
+            None => return write!(f, "error with service"),
+        };
+        match st.format {
+            StatusFormat::Daemontools => fmt_svstat(f, &self.name, st)?,
+            StatusFormat::Runit => fmt_sv(f, &self.name, st)?,
+            StatusFormat::S6 => fmt_s6_svstat(f, &self.name, st)?,
+        }
+
+        let now = Instant::now();
+        let counts = self.history.counts(now);
+        if counts.last_hour > 0 {
+            write!(f, ", {}", counts)?;
+        }
+        if self.history.is_crash_looping(now) {
+            write!(f, ", crash loop")?;
+        }
+        Ok(())
+    }
+}
+
+/// Displays a status in `sv status` form whatever supervisor wrote it.
+pub struct SvStatusLine<'a> {
+    name: &'a Path,
+    status: &'a ServiceStatus,
+}
+
+impl<'a> fmt::Display for SvStatusLine<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_sv(f, self.name, self.status)
+    }
+}
+
+impl ServiceStatus {
+    pub fn sv_line<'a>(&'a self, name: &'a Path) -> SvStatusLine<'a> {
+        SvStatusLine { name, status: self }
+    }
+}
+
+/// Formats a status the way daemontools `svstat` does.
+fn fmt_svstat(f: &mut fmt::Formatter, name: &Path, st: &ServiceStatus) -> fmt::Result {
+    match st.pid {
+        Some(p) => {
+            write!(f, "{}: up (pid {}) {} seconds", name.display(), p, st.duration)?;
+            if !st.normally_up {
+                write!(f, ", normally down")?;
+            }
+            if st.is_paused {
+                write!(f, ", paused")?;
+            }
+            if st.wants == Some(SvWants::WantsDown) {
+                write!(f, ", want down")?;
+            }
+            if st.readiness != Readiness::Unknown {
+                write!(f, ", {}", st.readiness)?;
+            }
+        }
+        None => {
+            write!(f, "{}: down {} seconds", name.display(), st.duration)?;
+            if st.normally_up {
+                write!(f, ", normally up")?;
+            }
+            if st.wants == Some(SvWants::WantsUp) {
+                write!(f, ", want up")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Formats a status the way runit `sv status` does.
+fn fmt_sv(f: &mut fmt::Formatter, name: &Path, st: &ServiceStatus) -> fmt::Result {
+    match st.run_state {
+        RunState::Down => write!(f, "down: {}: ", name.display())?,
+        RunState::Run => write!(f, "run: {}: ", name.display())?,
+        RunState::Finish => write!(f, "finish: {}: ", name.display())?,
+    }
+    if let Some(p) = st.pid {
+        write!(f, "(pid {}) ", p)?;
+    }
+    write!(f, "{}s", st.duration)?;
+    let up = st.pid.is_some();
+    if up && !st.normally_up {
+        write!(f, ", normally down")?;
+    }
+    if !up && st.normally_up {
+        write!(f, ", normally up")?;
+    }
+    if up && st.is_paused {
+        write!(f, ", paused")?;
+    }
+    if !up && st.wants == Some(SvWants::WantsUp) {
+        write!(f, ", want up")?;
+    }
+    if up && st.wants == Some(SvWants::WantsDown) {
+        write!(f, ", want down")?;
+    }
+    if up && st.got_term {
+        write!(f, ", got TERM")?;
+    }
+    if up && st.readiness != Readiness::Unknown {
+        write!(f, ", {}", st.readiness)?;
+    }
+    Ok(())
+}
+
+/// Formats a status the way `s6-svstat` does.
+fn fmt_s6_svstat(f: &mut fmt::Formatter, name: &Path, st: &ServiceStatus) -> fmt::Result {
+    let up = st.is_up();
+    match st.pid {
+        Some(p) if up => write!(f, "{}: up (pid {}) ", name.display(), p)?,
+        _ => {
+            write!(f, "{}: down ", name.display())?;
+            match st.last_exit {
+                Some(LastExit::Code(c)) => write!(f, "(exitcode {}) ", c)?,
+                Some(LastExit::Signal(s)) => write!(f, "(signal {}) ", signal_name(s))?,
+                None => {}
+            }
+        }
+    }
+    write!(f, "{} seconds", st.duration)?;
+    if up && !st.normally_up {
+        write!(f, ", normally down")?;
+    }
+    if !up && st.normally_up {
+        write!(f, ", normally up")?;
+    }
+    if up && st.is_paused {
+        write!(f, ", paused")?;
+    }
+    if !up && st.wants == Some(SvWants::WantsUp) {
+        write!(f, ", want up")?;
+    }
+    if up && st.wants == Some(SvWants::WantsDown) {
+        write!(f, ", want down")?;
+    }
+    if st.readiness != Readiness::Unknown && (up || st.readiness != Readiness::NotReady) {
+        write!(f, ", {}", st.readiness)?;
+    }
+    Ok(())
+}
+
+fn signal_name(sig: u8) -> String {
+    let name = match sig as i32 {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGUSR2 => "SIGUSR2",
+        _ => return sig.to_string(),
+    };
+    name.to_string()
+}
+
+/// Refreshes `service.status` from its service directory, recording any
+/// restart since the last refresh.
+pub fn update_supervise(service: &mut Service) -> &mut Service {
+    let status = ServiceStatus::read(&service.name).into();
+    if let Some(ref old) = service.status {
+        service.history.record(old, &status, Instant::now());
+    }
+    service.status = Some(status);
+    service
+}
+
+/// Checks the readiness of `service` if it is up and its supervisor does not
+/// track readiness. See [`ServiceStatus::check_readiness`].
+pub fn update_readiness(service: &mut Service) -> &mut Service {
+    if let Some(SvstatType::SvOk(ref mut st)) = service.status {
+        if let Ok(dir) = ServiceDir::open(&service.name) {
+            st.check_readiness(&dir);
+        }
+    }
+    service
+}
+
+fn decode(status_buf: &[u8], normally_up: bool, now: tai::Tai) -> Result<ServiceStatus, ErrorKind> {
+    match status_buf.len() {
+        DAEMONTOOLS_STATUS_LEN => decode_daemontools(status_buf, normally_up, now),
+        RUNIT_STATUS_LEN => decode_runit(status_buf, normally_up, now),
+        S6_STATUS_LEN | S6_PGID_STATUS_LEN => decode_s6(status_buf, normally_up, now),
+        _ => Err(ErrorKind::StatusBadFormat),
+    }
+}
+
+/// Decodes the layout shared by daemontools and runit: a TAI64N stamp, the
+/// pid in little-endian order, the paused flag and the wanted state.
+fn decode_daemontools(status_buf: &[u8], normally_up: bool, now: tai::Tai)
+                      -> Result<ServiceStatus, ErrorKind> {
+    let pid = get_pid(&status_buf[12..16]);
+
+    let want = status_buf[17] as char;
+    let paused = status_buf[16];
+
+    let since = unpack_stamp(&status_buf[0..12])?;
+
+    Ok(ServiceStatus {
+        pid: if pid != 0 { Some(pid) } else { None },
+        normally_up,
+        is_paused: paused != 0,
+        duration: (now - since.tai()).as_secs(),
+        since,
+        wants: match want {
+            'u' => Some(SvWants::WantsUp),
+            'd' => Some(SvWants::WantsDown),
+            _ => None,
+        },
+        format: StatusFormat::Daemontools,
+        run_state: if pid != 0 { RunState::Run } else { RunState::Down },
+        got_term: false,
+        last_exit: None,
+        readiness: Readiness::Unknown,
+    })
+}
+
+/// Decodes runit's layout: the daemontools one followed by the TERM flag
+/// and the run state (0 down, 1 run, 2 finish).
+fn decode_runit(status_buf: &[u8], normally_up: bool, now: tai::Tai)
+                -> Result<ServiceStatus, ErrorKind> {
+    let mut st = decode_daemontools(&status_buf[..DAEMONTOOLS_STATUS_LEN], normally_up, now)?;
+    st.format = StatusFormat::Runit;
+    st.got_term = status_buf[18] != 0;
+    st.run_state = match status_buf[19] {
+        1 => RunState::Run,
+        2 => RunState::Finish,
+        _ => RunState::Down,
+    };
+    Ok(st)
+}
+
+/// Decodes `s6_svstatus_pack`'s layout: the TAI64N stamp of the last state
+/// change, the TAI64N stamp of readiness, the pid as a big-endian 64-bit
+/// integer, from s6 2.10 the process group likewise, then the big-endian
+/// wait status and the flag byte.
+fn decode_s6(status_buf: &[u8], normally_up: bool, now: tai::Tai)
+             -> Result<ServiceStatus, ErrorKind> {
+    // skalibs stamps are true TAI, unlike the leap-second-free labels of
+    // daemontools and runit that `now` follows.
+    let now = tai::Leapsecs::bundled().add(now, false);
+    let len = status_buf.len();
+    let pid = get_u64_be(&status_buf[24..32]) as u32;
+    let wstat = (status_buf[len - 3] as u16) << 8 | status_buf[len - 2] as u16;
+    let flags = status_buf[len - 1];
+
+    let since = unpack_stamp(&status_buf[0..12])?;
+    let ready_since = unpack_stamp(&status_buf[12..24])?;
+
+    let finishing = flags & S6_FLAG_FINISHING != 0;
+    let run_state = match (pid, finishing) {
+        (0, _) => RunState::Down,
+        (_, true) => RunState::Finish,
+        (_, false) => RunState::Run,
+    };
+
+    Ok(ServiceStatus {
+        pid: if pid != 0 { Some(pid) } else { None },
+        normally_up,
+        is_paused: flags & S6_FLAG_PAUSED != 0,
+        duration: (now - since.tai()).as_secs(),
+        since,
+        wants: if flags & S6_FLAG_WANTUP != 0 {
+            Some(SvWants::WantsUp)
+        } else {
+            Some(SvWants::WantsDown)
+        },
+        format: StatusFormat::S6,
+        run_state,
+        got_term: false,
+        last_exit: if run_state == RunState::Run {
+            None
+        } else if wstat & 0x7f == 0 {
+            Some(LastExit::Code((wstat >> 8) as u8))
+        } else {
+            Some(LastExit::Signal((wstat & 0x7f) as u8))
+        },
+        readiness: if flags & S6_FLAG_READY != 0 {
+            Readiness::Ready(Some((now - ready_since.tai()).as_secs()))
+        } else {
+            Readiness::NotReady
+        },
+    })
+}
+
+fn unpack_stamp(packed: &[u8]) -> Result<tai::Tain, ErrorKind> {
+    tai::Tain::unpack(packed).map_err(|_| ErrorKind::StatusBadFormat)
+}
+
+fn get_u64_be(slice: &[u8]) -> u64 {
+    slice.iter().fold(0, |acc, &b| acc << 8 | b as u64)
+}
+
+fn get_pid(pid_slice: &[u8]) -> u32 {
+    let mut pid: u32 = pid_slice[3] as u32;
+    pid <<= 8;
+    pid += pid_slice[2] as u32;
+    pid <<= 8;
+    pid += pid_slice[1] as u32;
+    pid <<= 8;
+    pid += pid_slice[0] as u32;
+
+    pid
+}
+
+#[test]
+fn get_pid_little_endian() {
+    assert_eq!(0x04030201, get_pid(&[1, 2, 3, 4]));
+}
+
+#[test]
+fn decode_up_service() {
+    let mut buf = [0u8; 18];
+    buf[0..8].copy_from_slice(&[0x40, 0, 0, 0, 0, 0, 0, 100]);
+    buf[8..12].copy_from_slice(&[0, 0x0f, 0x42, 0x40]);
+    buf[12] = 123;
+    buf[17] = b'u';
+    let now = tai::unpack(&[0x40, 0, 0, 0, 0, 0, 0, 110]).unwrap();
+
+    let st = decode(&buf, true, now).unwrap();
+    assert_eq!(Some(123), st.pid);
+    assert_eq!(10, st.duration);
+    assert_eq!(1_000_000, st.since.subsec_nanos());
+    assert_eq!(Some(SvWants::WantsUp), st.wants);
+    assert!(!st.is_paused);
+}
+
+#[test]
+fn decode_clamps_future_timestamp() {
+    let mut buf = [0u8; 18];
+    buf[0..8].copy_from_slice(&[0x40, 0, 0, 0, 0, 0, 0, 200]);
+    let now = tai::unpack(&[0x40, 0, 0, 0, 0, 0, 0, 110]).unwrap();
+
+    let st = decode(&buf, false, now).unwrap();
+    assert_eq!(None, st.pid);
+    assert_eq!(0, st.duration);
+}
+
+#[test]
+fn decode_runit_finish() {
+    let mut buf = [0u8; 20];
+    buf[0..8].copy_from_slice(&[0x40, 0, 0, 0, 0, 0, 0, 100]);
+    buf[12] = 0x39;
+    buf[13] = 0x30;
+    buf[17] = b'd';
+    buf[18] = 1;
+    buf[19] = 2;
+    let now = tai::unpack(&[0x40, 0, 0, 0, 0, 0, 0, 103]).unwrap();
+
+    let st = decode(&buf, true, now).unwrap();
+    assert_eq!(StatusFormat::Runit, st.format);
+    assert_eq!(RunState::Finish, st.run_state);
+    assert_eq!(Some(12345), st.pid);
+    assert!(st.got_term);
+
+    let mut service = Service::new("/service/foo");
+    service.status = Some(SvstatType::SvOk(st));
+    assert_eq!("finish: /service/foo: (pid 12345) 3s, want down, got TERM",
+               service.to_string());
+}
+
+#[test]
+fn decode_rejects_unknown_length() {
+    let now = tai::unpack(&[0x40, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+    assert_eq!(Err(ErrorKind::StatusBadFormat), decode(&[0u8; 19], true, now));
+}
+
+#[test]
+fn decode_s6_up_and_ready() {
+    let mut buf = [0u8; 43];
+    buf[0..8].copy_from_slice(&[0x40, 0, 0, 0, 0, 0, 0, 100]);
+    buf[12..20].copy_from_slice(&[0x40, 0, 0, 0, 0, 0, 0, 130]);
+    buf[30] = 0x30;
+    buf[31] = 0x39;
+    buf[42] = S6_FLAG_WANTUP | S6_FLAG_READY;
+    let now = tai::unpack(&[0x40, 0, 0, 0, 0, 0, 0, 140]).unwrap();
+
+    let st = decode(&buf, false, now).unwrap();
+    assert_eq!(StatusFormat::S6, st.format);
+    assert_eq!(Some(12345), st.pid);
+    assert_eq!(40, st.duration);
+    assert_eq!(Readiness::Ready(Some(10)), st.readiness);
+    assert_eq!(None, st.last_exit);
+
+    let mut service = Service::new("/service/foo");
+    service.status = Some(SvstatType::SvOk(st));
+    assert_eq!("/service/foo: up (pid 12345) 40 seconds, normally down, ready 10 seconds",
+               service.to_string());
+}
+
+#[test]
+fn decode_s6_killed_by_signal() {
+    let mut buf = [0u8; 35];
+    buf[0..8].copy_from_slice(&[0x40, 0, 0, 0, 0, 0, 0, 100]);
+    buf[33] = libc::SIGKILL as u8;
+    buf[34] = S6_FLAG_WANTUP;
+    let now = tai::unpack(&[0x40, 0, 0, 0, 0, 0, 0, 105]).unwrap();
+
+    let st = decode(&buf, true, now).unwrap();
+    assert_eq!(RunState::Down, st.run_state);
+    assert_eq!(Some(LastExit::Signal(libc::SIGKILL as u8)), st.last_exit);
+
+    let mut service = Service::new("/service/foo");
+    service.status = Some(SvstatType::SvOk(st));
+    assert_eq!("/service/foo: down (signal SIGKILL) 5 seconds, normally up, want up",
+               service.to_string());
+}
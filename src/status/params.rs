@@ -0,0 +1,86 @@
+//! The `ServiceStatus` params of `interface.txt`, as JSON.
+
+use std::time::Instant;
+
+use serde_json::{Map, Value};
+
+use super::{Readiness, Service, SvstatType};
+
+/// Describes `service` with the `ServiceStatus` params of `interface.txt`.
+///
+/// A service whose status could not be read has `isUp` false, null `pid`,
+/// `normallyup` and `duration`, and the reason in `error`.
+pub fn status_params(service: &Service) -> Value {
+    let mut map = Map::new();
+    let name = service.name.file_name().unwrap_or(service.name.as_os_str());
+    map.insert("service".to_string(), Value::String(name.to_string_lossy().into_owned()));
+    map.insert("path".to_string(),
+               Value::String(service.name.to_string_lossy().into_owned()));
+
+    match service.status {
+        Some(SvstatType::SvOk(ref st)) => {
+            map.insert("pid".to_string(), st.pid.map_or(Value::Null, |p| Value::U64(p as u64)));
+            map.insert("isUp".to_string(), Value::Bool(st.is_up()));
+            map.insert("normallyup".to_string(), Value::Bool(st.normally_up));
+            map.insert("duration".to_string(), Value::U64(st.duration));
+            let (readiness, ready_for) = match st.readiness {
+                Readiness::Ready(secs) => ("ready", secs.map_or(Value::Null, Value::U64)),
+                Readiness::NotReady => ("not ready", Value::Null),
+                Readiness::Unknown => ("unknown", Value::Null),
+            };
+            map.insert("readiness".to_string(), Value::String(readiness.to_string()));
+            map.insert("readyFor".to_string(), ready_for);
+        }
+        ref other => {
+            map.insert("pid".to_string(), Value::Null);
+            map.insert("isUp".to_string(), Value::Bool(false));
+            map.insert("normallyup".to_string(), Value::Null);
+            map.insert("duration".to_string(), Value::Null);
+            let error = match *other {
+                Some(SvstatType::SvError(ref e)) => e.to_string(),
+                _ => "status not read".to_string(),
+            };
+            map.insert("error".to_string(), Value::String(error));
+        }
+    }
+
+    let now = Instant::now();
+    let counts = service.history.counts(now);
+    let mut restarts = Map::new();
+    restarts.insert("1m".to_string(), Value::U64(counts.last_minute as u64));
+    restarts.insert("10m".to_string(), Value::U64(counts.last_ten_minutes as u64));
+    restarts.insert("1h".to_string(), Value::U64(counts.last_hour as u64));
+    map.insert("restarts".to_string(), Value::Object(restarts));
+    map.insert("crashLoop".to_string(), Value::Bool(service.history.is_crash_looping(now)));
+    Value::Object(map)
+}
+
+#[test]
+fn params_use_interface_names() {
+    use super::ServiceStatus;
+    use testing;
+
+    let mut service = Service::new("/service/web");
+    service.status = Some(SvstatType::SvOk(testing::status(None)));
+    let restarted = SvstatType::SvOk(ServiceStatus {
+        readiness: Readiness::Ready(Some(2)),
+        ..testing::status(Some(123))
+    });
+    service.history.record(service.status.as_ref().unwrap(), &restarted, Instant::now());
+    service.status = Some(restarted);
+
+    let params = status_params(&service);
+    let map = params.as_object().unwrap();
+    assert_eq!(Some(&Value::String("web".to_string())), map.get("service"));
+    assert_eq!(Some(&Value::String("/service/web".to_string())), map.get("path"));
+    assert_eq!(Some(&Value::U64(123)), map.get("pid"));
+    assert_eq!(Some(&Value::Bool(true)), map.get("isUp"));
+    assert_eq!(Some(&Value::Bool(true)), map.get("normallyup"));
+    assert_eq!(Some(&Value::U64(0)), map.get("duration"));
+    assert_eq!(Some(&Value::String("ready".to_string())), map.get("readiness"));
+    assert_eq!(Some(&Value::U64(2)), map.get("readyFor"));
+    assert_eq!(Some(&Value::Bool(false)), map.get("crashLoop"));
+    let restarts = map.get("restarts").and_then(Value::as_object).unwrap();
+    assert_eq!(3, restarts.len());
+    assert!(restarts.values().all(|n| n.as_u64().is_some()));
+}
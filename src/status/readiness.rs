@@ -0,0 +1,152 @@
+use std::fmt;
+use std::fs;
+use std::io::prelude::*;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use super::{ServiceDir, ServiceStatus};
+
+/// How long a `check` script may run before the service is taken as not
+/// ready.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+const CHECK_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Whether an up service is ready to be used.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Readiness {
+    /// Nothing tells: the supervisor does not track readiness and the
+    /// service has no check configured.
+    Unknown,
+    NotReady,
+    /// Ready, for this many seconds when that is known.
+    Ready(Option<u64>),
+}
+
+impl fmt::Display for Readiness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Readiness::Unknown => write!(f, "readiness unknown"),
+            Readiness::NotReady => write!(f, "not ready"),
+            Readiness::Ready(Some(secs)) => write!(f, "ready {} seconds", secs),
+            Readiness::Ready(None) => write!(f, "ready"),
+        }
+    }
+}
+
+/// Works out the readiness of an up service from the checks configured in
+/// its directory, for supervisors that do not track it themselves.
+///
+/// A `ready-file` file names a file, relative to the service directory,
+/// that the service creates once it is ready; one left over from before the
+/// service started does not count. Otherwise an executable `check` script
+/// is run, as `sv check` does, and the service is ready if it exits 0.
+pub(crate) fn check(dir: &ServiceDir, st: &ServiceStatus) -> Readiness {
+    if let Ok(mut f) = dir.open_read("ready-file") {
+        let mut name = String::new();
+        if f.read_to_string(&mut name).is_err() {
+            return Readiness::NotReady;
+        }
+        return check_file(dir, name.lines().next().unwrap_or("").trim(), st);
+    }
+    match dir.exists("check") {
+        Ok(true) => check_script(dir),
+        _ => Readiness::Unknown,
+    }
+}
+
+fn check_file(dir: &ServiceDir, name: &str, st: &ServiceStatus) -> Readiness {
+    if name.is_empty() {
+        return Readiness::NotReady;
+    }
+    let modified = match fs::metadata(dir.path().join(name)).and_then(|m| m.modified()) {
+        Ok(modified) => modified,
+        Err(_) => return Readiness::NotReady,
+    };
+    // The duration is in whole seconds, so allow for the part second it
+    // leaves out.
+    let now = SystemTime::now();
+    let started = now.checked_sub(Duration::from_secs(st.duration + 1))
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    if modified < started {
+        return Readiness::NotReady;
+    }
+    let secs = now.duration_since(modified).map(|d| d.as_secs()).unwrap_or(0);
+    Readiness::Ready(Some(secs.min(st.duration)))
+}
+
+fn check_script(dir: &ServiceDir) -> Readiness {
+    // Run from the service directory, where a relative service path would
+    // no longer lead to it.
+    let child = Command::new("./check")
+        .current_dir(dir.path())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(_) => return Readiness::NotReady,
+    };
+
+    let deadline = Instant::now() + CHECK_TIMEOUT;
+    loop {
+        match child.try_wait() {
+            Ok(Some(exit)) if exit.success() => return Readiness::Ready(None),
+            Ok(None) if Instant::now() < deadline => thread::sleep(CHECK_POLL_INTERVAL),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Readiness::NotReady;
+            }
+            _ => return Readiness::NotReady,
+        }
+    }
+}
+
+#[test]
+fn ready_file_must_be_newer_than_start() {
+    use testing;
+
+    let path = ::std::env::temp_dir().join(format!("rupervise-ready-{}", ::std::process::id()));
+    fs::create_dir_all(&path).unwrap();
+    fs::write(path.join("ready-file"), "run/ready\n").unwrap();
+    let dir = ServiceDir::open(&path).unwrap();
+
+    let st = ServiceStatus { duration: 30, ..testing::status(Some(10)) };
+    assert_eq!(Readiness::NotReady, check(&dir, &st));
+
+    fs::create_dir_all(path.join("run")).unwrap();
+    fs::write(path.join("run/ready"), "").unwrap();
+    assert_eq!(Readiness::Ready(Some(0)), check(&dir, &st));
+
+    // Left over from a run that ended before this one started.
+    {
+        use std::os::unix::io::AsRawFd;
+
+        let ready = fs::File::open(path.join("run/ready")).unwrap();
+        let then = SystemTime::now() - Duration::from_secs(60);
+        let then = libc::timespec {
+            tv_sec: then.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs() as libc::time_t,
+            tv_nsec: 0,
+        };
+        assert_eq!(0, unsafe { libc::futimens(ready.as_raw_fd(), [then, then].as_ptr()) });
+    }
+    assert_eq!(Readiness::NotReady, check(&dir, &st));
+    fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn check_script_in_relative_dir() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Relative to the crate, where tests run, and nowhere else.
+    let path = ::std::path::PathBuf::from(format!("target/rupervise-check-{}",
+                                                  ::std::process::id()));
+    fs::create_dir_all(&path).unwrap();
+    fs::write(path.join("check"), "#!/bin/sh\nexit 0\n").unwrap();
+    fs::set_permissions(path.join("check"), fs::Permissions::from_mode(0o755)).unwrap();
+
+    assert_eq!(Readiness::Ready(None), check_script(&ServiceDir::open(&path).unwrap()));
+    fs::remove_dir_all(&path).unwrap();
+}
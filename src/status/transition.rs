@@ -0,0 +1,162 @@
+use std::fmt;
+
+use super::{ErrorKind, ServiceStatus, SvWants, SvstatType};
+
+/// A change between two status snapshots of the same service.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transition {
+    /// The service was started.
+    Up { pid: u32 },
+    /// The service stopped; `pid` is the process that exited.
+    Down { pid: u32 },
+    /// The service stayed up, but a new process replaced the old one.
+    Restarted { old_pid: u32, new_pid: u32 },
+    Paused,
+    Resumed,
+    WantChanged { from: Option<SvWants>, to: Option<SvWants> },
+    /// The supervisor stopped running.
+    SuperviseGone,
+}
+
+impl fmt::Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Transition::Up { pid } => write!(f, "up (pid {})", pid),
+            Transition::Down { pid } => write!(f, "down (was pid {})", pid),
+            Transition::Restarted { old_pid, new_pid } => {
+                write!(f, "restarted (pid {} -> {})", old_pid, new_pid)
+            }
+            Transition::Paused => write!(f, "paused"),
+            Transition::Resumed => write!(f, "resumed"),
+            Transition::WantChanged { to, .. } => match to {
+                Some(SvWants::WantsUp) => write!(f, "want up"),
+                Some(SvWants::WantsDown) => write!(f, "want down"),
+                None => write!(f, "want nothing"),
+            },
+            Transition::SuperviseGone => write!(f, "supervise not running"),
+        }
+    }
+}
+
+/// Lists the transitions that lead from the `old` snapshot to `new`.
+///
+/// When either snapshot failed to read, only the supervisor going away and
+/// the service being found up again are reported; nothing else can be told
+/// apart from an unreadable status.
+pub fn transitions(old: &SvstatType, new: &SvstatType) -> Vec<Transition> {
+    match (old, new) {
+        (SvstatType::SvOk(o), SvstatType::SvOk(n)) => status_transitions(o, n),
+        (SvstatType::SvOk(_), SvstatType::SvError(e)) => {
+            if e.kind() == ErrorKind::SuperviseNotRunning {
+                vec![Transition::SuperviseGone]
+            } else {
+                Vec::new()
+            }
+        }
+        (SvstatType::SvError(_), SvstatType::SvOk(n)) => match n.pid {
+            Some(pid) if n.is_up() => vec![Transition::Up { pid }],
+            _ => Vec::new(),
+        },
+        (SvstatType::SvError(_), SvstatType::SvError(_)) => Vec::new(),
+    }
+}
+
+fn status_transitions(old: &ServiceStatus, new: &ServiceStatus) -> Vec<Transition> {
+    let mut changes = Vec::new();
+
+    match (old.is_up(), new.is_up(), old.pid, new.pid) {
+        (false, true, _, Some(pid)) => changes.push(Transition::Up { pid }),
+        (true, false, Some(pid), _) => changes.push(Transition::Down { pid }),
+        // A restart between snapshots may reuse the pid, but it always moves
+        // the status stamp.
+        (true, true, Some(old_pid), Some(new_pid))
+            if old_pid != new_pid || new.since > old.since => {
+            changes.push(Transition::Restarted { old_pid, new_pid })
+        }
+        _ => {}
+    }
+
+    match (old.is_paused, new.is_paused) {
+        (false, true) => changes.push(Transition::Paused),
+        (true, false) => changes.push(Transition::Resumed),
+        _ => {}
+    }
+
+    if old.wants != new.wants {
+        changes.push(Transition::WantChanged {
+            from: old.wants,
+            to: new.wants,
+        });
+    }
+
+    changes
+}
+
+#[cfg(test)]
+fn up_status(pid: Option<u32>) -> ServiceStatus {
+    use tai::{Tai, Tain};
+    use testing;
+
+    ServiceStatus {
+        since: Tain::from("@4000000058060a2b".parse::<Tai>().unwrap()),
+        ..testing::status(pid)
+    }
+}
+
+#[test]
+fn no_change_no_transitions() {
+    let a = SvstatType::SvOk(up_status(Some(10)));
+    assert_eq!(Vec::<Transition>::new(), transitions(&a, &a.clone()));
+}
+
+#[test]
+fn up_down_and_restart() {
+    let down = SvstatType::SvOk(up_status(None));
+    let up = SvstatType::SvOk(up_status(Some(10)));
+    let restarted = SvstatType::SvOk(up_status(Some(11)));
+
+    assert_eq!(vec![Transition::Up { pid: 10 }], transitions(&down, &up));
+    assert_eq!(vec![Transition::Down { pid: 10 }], transitions(&up, &down));
+    assert_eq!(vec![Transition::Restarted { old_pid: 10, new_pid: 11 }],
+               transitions(&up, &restarted));
+}
+
+#[test]
+fn restart_with_reused_pid() {
+    let before = up_status(Some(10));
+    let mut after = before.clone();
+    after.since = after.since + ::std::time::Duration::from_secs(3);
+
+    assert_eq!(vec![Transition::Restarted { old_pid: 10, new_pid: 10 }],
+               transitions(&SvstatType::SvOk(before), &SvstatType::SvOk(after)));
+}
+
+#[test]
+fn pause_and_want() {
+    let before = up_status(Some(10));
+    let mut after = before.clone();
+    after.is_paused = true;
+    after.wants = Some(SvWants::WantsDown);
+
+    assert_eq!(vec![Transition::Paused,
+                    Transition::WantChanged {
+                        from: Some(SvWants::WantsUp),
+                        to: Some(SvWants::WantsDown),
+                    }],
+               transitions(&SvstatType::SvOk(before.clone()), &SvstatType::SvOk(after.clone())));
+    assert_eq!(Transition::Resumed,
+               transitions(&SvstatType::SvOk(after), &SvstatType::SvOk(before))[0]);
+}
+
+#[test]
+fn supervise_gone() {
+    use super::SvstatError;
+
+    let up = SvstatType::SvOk(up_status(Some(10)));
+    let gone = SvstatType::SvError(SvstatError::new(ErrorKind::SuperviseNotRunning, "/service/foo"));
+    let unreadable = SvstatType::SvError(SvstatError::new(ErrorKind::StatusBadFormat, "/service/foo"));
+
+    assert_eq!(vec![Transition::SuperviseGone], transitions(&up, &gone));
+    assert!(transitions(&up, &unreadable).is_empty());
+    assert_eq!(vec![Transition::Up { pid: 10 }], transitions(&gone, &up));
+}
@@ -0,0 +1,211 @@
+//! External TAI64 and TAI64N labels: `@` followed by 16 or 24 lowercase hex
+//! digits, as written by `tai64n` and `multilog t`.
+
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
+
+use libc;
+
+use super::{Leapsecs, Tai, Tain, TAI_OFFSET};
+
+const TAI64_HEX_LEN: usize = 16;
+const TAI64N_HEX_LEN: usize = 24;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ParseLabelError {
+    /// The label is not 16 or 24 hex digits long.
+    BadLength,
+    /// The label contains something other than a hex digit.
+    BadDigit,
+    /// The nanosecond field is a billion or more.
+    BadNanos,
+}
+
+impl fmt::Display for ParseLabelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseLabelError::BadLength => write!(f, "label is not 16 or 24 hex digits"),
+            ParseLabelError::BadDigit => write!(f, "label contains a non-hex character"),
+            ParseLabelError::BadNanos => write!(f, "label has more than 999999999 nanoseconds"),
+        }
+    }
+}
+
+impl Error for ParseLabelError {}
+
+fn parse_hex(digits: &str) -> Result<u64, ParseLabelError> {
+    if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ParseLabelError::BadDigit);
+    }
+    u64::from_str_radix(digits, 16).map_err(|_| ParseLabelError::BadDigit)
+}
+
+impl FromStr for Tain {
+    type Err = ParseLabelError;
+
+    /// Parses a TAI64N label, or a TAI64 label with zero nanoseconds. The
+    /// leading `@` is optional.
+    fn from_str(s: &str) -> Result<Tain, ParseLabelError> {
+        let hex = s.strip_prefix('@').unwrap_or(s);
+        // Checked before slicing, which must land on a character boundary.
+        if !hex.is_ascii() {
+            return Err(ParseLabelError::BadDigit);
+        }
+        match hex.len() {
+            TAI64_HEX_LEN => Ok(Tain::from(Tai(parse_hex(hex)?))),
+            TAI64N_HEX_LEN => {
+                let sec = parse_hex(&hex[..TAI64_HEX_LEN])?;
+                let nano = parse_hex(&hex[TAI64_HEX_LEN..])? as u32;
+                if nano >= 1_000_000_000 {
+                    return Err(ParseLabelError::BadNanos);
+                }
+                Ok(Tain::new(Tai(sec), nano))
+            }
+            _ => Err(ParseLabelError::BadLength),
+        }
+    }
+}
+
+impl FromStr for Tai {
+    type Err = ParseLabelError;
+
+    /// Parses a TAI64 label. The leading `@` is optional.
+    fn from_str(s: &str) -> Result<Tai, ParseLabelError> {
+        let hex = s.strip_prefix('@').unwrap_or(s);
+        if hex.len() != TAI64_HEX_LEN {
+            return Err(ParseLabelError::BadLength);
+        }
+        Ok(Tai(parse_hex(hex)?))
+    }
+}
+
+impl fmt::Display for Tain {
+    /// Formats the TAI64N label, e.g. `@4000000058060a2b1d2e4a30`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "@{:016x}{:08x}", self.sec.0, self.nano)
+    }
+}
+
+impl fmt::Display for Tai {
+    /// Formats the TAI64 label, e.g. `@4000000058060a2b`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "@{:016x}", self.0)
+    }
+}
+
+impl Tain {
+    /// Splits a leading TAI64N label and the space after it off a log line,
+    /// the way `tai64nlocal` recognises them.
+    pub fn split_label(line: &str) -> Option<(Tain, &str)> {
+        let end = TAI64N_HEX_LEN + 1;
+        if !line.starts_with('@') || line.len() < end || !line.is_char_boundary(end) {
+            return None;
+        }
+        let t = line[..end].parse().ok()?;
+        let rest = &line[end..];
+        Some((t, rest.strip_prefix(' ').unwrap_or(rest)))
+    }
+
+    /// Formats the label as UTC, `YYYY-MM-DD HH:MM:SS.NNNNNNNNN`.
+    pub fn format_utc(&self, leaps: &Leapsecs) -> String {
+        let (secs, hit) = unix_secs(*self, leaps);
+        let days = secs.div_euclid(86400);
+        let rem = secs.rem_euclid(86400);
+        let (y, m, d) = civil_from_days(days);
+        format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:09}",
+                y, m, d, rem / 3600, rem / 60 % 60, rem % 60 + hit as i64, self.nano)
+    }
+
+    /// Formats the label in the local time zone, the way `tai64nlocal` does.
+    pub fn format_local(&self, leaps: &Leapsecs) -> String {
+        let (secs, hit) = unix_secs(*self, leaps);
+        let t = secs as libc::time_t;
+        let mut tm: libc::tm = unsafe { mem::zeroed() };
+        if unsafe { libc::localtime_r(&t, &mut tm) }.is_null() {
+            return self.format_utc(leaps);
+        }
+        format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:09}",
+                tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday,
+                tm.tm_hour, tm.tm_min, tm.tm_sec + hit as i32, self.nano)
+    }
+}
+
+/// Converts a label to POSIX seconds; the flag marks a leap second.
+fn unix_secs(t: Tain, leaps: &Leapsecs) -> (i64, bool) {
+    let (utc, hit) = leaps.sub(t.sec);
+    (utc.0.wrapping_sub(TAI_OFFSET) as i64, hit)
+}
+
+/// Converts days since 1970-01-01 to a proleptic Gregorian date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m, d)
+}
+
+/// Replaces a leading TAI64N label on `line` with local time, like
+/// `tai64nlocal`. Lines without a label are returned unchanged.
+pub fn tai64nlocal(line: &str, leaps: &Leapsecs) -> String {
+    match Tain::split_label(line) {
+        Some((t, rest)) => format!("{} {}", t.format_local(leaps), rest),
+        None => line.to_string(),
+    }
+}
+
+#[test]
+fn parse_tai64n_label() {
+    let t: Tain = "@4000000058060a2b1d2e4a30".parse().unwrap();
+    assert_eq!(Tai(0x4000000058060a2b), t.tai());
+    assert_eq!(0x1d2e4a30, t.subsec_nanos());
+    assert_eq!("@4000000058060a2b1d2e4a30", t.to_string());
+}
+
+#[test]
+fn parse_tai64_label() {
+    let t: Tai = "@4000000058060a2b".parse().unwrap();
+    assert_eq!(Tai(0x4000000058060a2b), t);
+    assert_eq!(Tain::from(t), "4000000058060a2b".parse().unwrap());
+}
+
+#[test]
+fn parse_bad_labels() {
+    assert_eq!(Err(ParseLabelError::BadLength), "@4000".parse::<Tain>());
+    assert_eq!(Err(ParseLabelError::BadDigit), "@400000005806+a2b".parse::<Tai>());
+    assert_eq!(Err(ParseLabelError::BadDigit), "@400000005806a2b\u{e9}0000000".parse::<Tain>());
+    assert_eq!(Err(ParseLabelError::BadNanos), "@4000000058060a2b3b9aca00".parse::<Tain>());
+}
+
+#[test]
+fn format_multilog_label_as_utc() {
+    // multilog stamps carry no leap seconds.
+    let t: Tain = "@4000000058060a2b00000007".parse().unwrap();
+    assert_eq!("2016-10-18 11:40:17.000000007", t.format_utc(&Leapsecs::empty()));
+}
+
+#[test]
+fn format_leap_second_as_utc() {
+    let leap = Tain::from(Tai(TAI_OFFSET + 1483228800 + 26));
+    assert_eq!("2016-12-31 23:59:60.000000000", leap.format_utc(Leapsecs::bundled()));
+    let after = Tain::from(Tai(TAI_OFFSET + 1483228800 + 27));
+    assert_eq!("2017-01-01 00:00:00.000000000", after.format_utc(Leapsecs::bundled()));
+}
+
+#[test]
+fn split_log_line() {
+    let (t, rest) = Tain::split_label("@4000000058060a2b00000007 starting").unwrap();
+    assert_eq!(7, t.subsec_nanos());
+    assert_eq!("starting", rest);
+    assert!(Tain::split_label("starting").is_none());
+    assert!(Tain::split_label("@400000005806a2b\u{e9}0000000 hello").is_none());
+    assert_eq!("@400000005806a2b\u{e9}0000000 hello",
+               tai64nlocal("@400000005806a2b\u{e9}0000000 hello", Leapsecs::bundled()));
+}
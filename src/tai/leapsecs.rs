@@ -0,0 +1,195 @@
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{unpack, Tai, TaiError, Tain, TAI_OFFSET};
+
+/// UTC midnights, as POSIX times, that directly follow each leap second
+/// inserted since 1972.
+const LEAP_MIDNIGHTS: [u64; 27] = [
+    78796800,   // 1972-07-01
+    94694400,   // 1973-01-01
+    126230400,  // 1974-01-01
+    157766400,  // 1975-01-01
+    189302400,  // 1976-01-01
+    220924800,  // 1977-01-01
+    252460800,  // 1978-01-01
+    283996800,  // 1979-01-01
+    315532800,  // 1980-01-01
+    362793600,  // 1981-07-01
+    394329600,  // 1982-07-01
+    425865600,  // 1983-07-01
+    489024000,  // 1985-07-01
+    567993600,  // 1988-01-01
+    631152000,  // 1990-01-01
+    662688000,  // 1991-01-01
+    709948800,  // 1992-07-01
+    741484800,  // 1993-07-01
+    773020800,  // 1994-07-01
+    820454400,  // 1996-01-01
+    867715200,  // 1997-07-01
+    915148800,  // 1999-01-01
+    1136073600, // 2006-01-01
+    1230768000, // 2009-01-01
+    1341100800, // 2012-07-01
+    1435708800, // 2015-07-01
+    1483228800, // 2017-01-01
+];
+
+/// A table of leap seconds, used to convert between TAI and UTC.
+///
+/// As in libtai, each entry is the TAI64 label of an inserted leap second,
+/// and UTC times are labelled `2^62 + 10 + POSIX seconds` before the table
+/// is applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Leapsecs {
+    table: Vec<Tai>,
+}
+
+impl Leapsecs {
+    /// The leap seconds known when this crate was released.
+    pub fn bundled() -> &'static Leapsecs {
+        static BUNDLED: OnceLock<Leapsecs> = OnceLock::new();
+        BUNDLED.get_or_init(|| {
+            let table = LEAP_MIDNIGHTS.iter()
+                .enumerate()
+                .map(|(i, &midnight)| Tai(TAI_OFFSET + midnight + i as u64))
+                .collect();
+            Leapsecs { table }
+        })
+    }
+
+    /// A table without leap seconds, which treats TAI as UTC plus ten
+    /// seconds. This is the convention of daemontools, whose `tai64nlocal`
+    /// and `multilog` ignore leap seconds.
+    pub fn empty() -> Leapsecs {
+        Leapsecs { table: Vec::new() }
+    }
+
+    /// Loads a `leapsecs.dat` file in the libtai format: packed 8-byte TAI64
+    /// labels in increasing order.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Leapsecs> {
+        let mut buf = Vec::new();
+        File::open(path)?.read_to_end(&mut buf)?;
+        Leapsecs::parse(&buf)
+    }
+
+    pub fn parse(packed: &[u8]) -> io::Result<Leapsecs> {
+        if packed.len() % 8 != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "leapsecs table is not a multiple of 8 bytes"));
+        }
+        let table: Vec<Tai> = packed.chunks(8)
+            .map(unpack)
+            .collect::<Result<_, _>>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if table.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "leapsecs table is not in increasing order"));
+        }
+        Ok(Leapsecs { table })
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Converts a UTC label to TAI, like libtai's `leapsecs_add`. `hit` marks
+    /// `t` as the 23:59:60 second itself.
+    pub fn add(&self, t: Tai, hit: bool) -> Tai {
+        let mut u = t.0;
+        for leap in &self.table {
+            if u < leap.0 {
+                break;
+            }
+            if !hit || u > leap.0 {
+                u += 1;
+            }
+        }
+        Tai(u)
+    }
+
+    /// Converts a TAI label to UTC, like libtai's `leapsecs_sub`. The flag is
+    /// set when `t` is a leap second, which UTC labels as the second before.
+    pub fn sub(&self, t: Tai) -> (Tai, bool) {
+        let u = t.0;
+        let mut s = 0;
+        for leap in &self.table {
+            if u < leap.0 {
+                break;
+            }
+            s += 1;
+            if u == leap.0 {
+                return (Tai(u - s), true);
+            }
+        }
+        (Tai(u - s), false)
+    }
+
+    pub fn from_system_time(&self, t: SystemTime) -> Tain {
+        let utc = match t.duration_since(UNIX_EPOCH) {
+            Ok(d) => Tain::new(Tai(TAI_OFFSET + d.as_secs()), d.subsec_nanos()),
+            Err(e) => Tain::from(Tai(TAI_OFFSET)) - e.duration(),
+        };
+        Tain::new(self.add(utc.tai(), false), utc.subsec_nanos())
+    }
+
+    pub fn to_system_time(&self, t: Tain) -> Result<SystemTime, TaiError> {
+        let (utc, _) = self.sub(t.tai());
+        let nanos = Duration::new(0, t.subsec_nanos());
+        let secs = if utc.0 >= TAI_OFFSET {
+            UNIX_EPOCH.checked_add(Duration::from_secs(utc.0 - TAI_OFFSET))
+        } else {
+            UNIX_EPOCH.checked_sub(Duration::from_secs(TAI_OFFSET - utc.0))
+        };
+        secs.and_then(|t| t.checked_add(nanos)).ok_or(TaiError::OutOfRange)
+    }
+}
+
+#[test]
+fn bundled_offset_today() {
+    // TAI has been 37 seconds ahead of UTC since 2017.
+    let t = UNIX_EPOCH + Duration::from_secs(1500000000);
+    let tai = Leapsecs::bundled().from_system_time(t);
+    assert_eq!(TAI_OFFSET - 10 + 1500000000 + 37, tai.tai().as_secs());
+}
+
+#[test]
+fn add_and_sub_around_a_leap() {
+    let leaps = Leapsecs::bundled();
+    let midnight = Tai(TAI_OFFSET + 78796800);
+
+    assert_eq!(Tai(midnight.0 - 1), leaps.add(Tai(midnight.0 - 1), false));
+    assert_eq!(Tai(midnight.0 + 1), leaps.add(midnight, false));
+    assert_eq!(midnight, leaps.add(midnight, true));
+
+    assert_eq!((Tai(midnight.0 - 1), true), leaps.sub(midnight));
+    assert_eq!((midnight, false), leaps.sub(Tai(midnight.0 + 1)));
+}
+
+#[test]
+fn system_time_round_trip() {
+    let leaps = Leapsecs::bundled();
+    let t = UNIX_EPOCH + Duration::new(1483228800, 250);
+    assert_eq!(Ok(t), leaps.to_system_time(leaps.from_system_time(t)));
+
+    let before_epoch = UNIX_EPOCH - Duration::from_secs(86400);
+    assert_eq!(Ok(before_epoch), leaps.to_system_time(leaps.from_system_time(before_epoch)));
+}
+
+#[test]
+fn parse_libtai_format() {
+    let mut packed = Vec::new();
+    for leap in &Leapsecs::bundled().table {
+        packed.extend_from_slice(&leap.0.to_be_bytes());
+    }
+    assert_eq!(Leapsecs::bundled(), &Leapsecs::parse(&packed).unwrap());
+    assert!(Leapsecs::parse(&packed[1..]).is_err());
+}
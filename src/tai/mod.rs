@@ -1,24 +1,68 @@
-use std::time::{Duration,SystemTime};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+mod label;
+mod leapsecs;
+pub use self::label::{tai64nlocal, ParseLabelError};
+pub use self::leapsecs::Leapsecs;
 
 const TAI_OFFSET: u64 = 4611686018427387914;
 
 #[derive(Debug,PartialEq, Copy, Clone, Eq, Ord, PartialOrd)]
 pub struct Tai(u64);
 
-impl ::std::ops::Add for Tai {
+/// A whole number of TAI seconds: the distance between two `Tai` labels.
+#[derive(Debug, PartialEq, Copy, Clone, Eq, Ord, PartialOrd, Default)]
+pub struct TaiDuration(u64);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TaiError {
+    /// The packed input is shorter than the format requires.
+    ShortInput { needed: usize, got: usize },
+    /// The label is outside the range `SystemTime` can represent.
+    OutOfRange,
+}
+
+impl fmt::Display for TaiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TaiError::ShortInput { needed, got } => {
+                write!(f, "packed label needs {} bytes, got {}", needed, got)
+            }
+            TaiError::OutOfRange => write!(f, "label is out of the range of SystemTime"),
+        }
+    }
+}
+
+impl Error for TaiError {}
+
+/// Adds `_rhs`, saturating at the largest label.
+impl ::std::ops::Add<TaiDuration> for Tai {
     type Output = Tai;
 
-    fn add(self, _rhs: Tai) -> Tai {
-        Tai(self.0 + _rhs.0)
+    fn add(self, _rhs: TaiDuration) -> Tai {
+        self.saturating_add(_rhs)
     }
 }
 
+/// Subtracts `_rhs`, saturating at label zero.
+impl ::std::ops::Sub<TaiDuration> for Tai {
+    type Output = Tai;
 
+    fn sub(self, _rhs: TaiDuration) -> Tai {
+        self.saturating_sub(_rhs)
+    }
+}
+
+/// The time from `_rhs` to `self`, or zero if `_rhs` is later, as with
+/// `std::time::Instant`.
 impl ::std::ops::Sub for Tai {
-    type Output = Tai;
+    type Output = TaiDuration;
 
-    fn sub(self, _rhs: Tai) -> Tai {
-        Tai(self.0 - _rhs.0)
+    fn sub(self, _rhs: Tai) -> TaiDuration {
+        self.saturating_duration_since(_rhs)
     }
 }
 
@@ -26,13 +70,19 @@ fn tai_unix(unix_time: u64) -> Tai {
     Tai(TAI_OFFSET + unix_time)
 }
 
+/// The current time, labelled the way daemontools' `tai_now` does: POSIX
+/// time plus `TAI_OFFSET`, without leap seconds. This matches the stamps
+/// `supervise` and `runsv` write; use `Tai::from_system_time` for true TAI.
 pub fn now() -> Tai {
-    let now = ::std::time::UNIX_EPOCH.elapsed().unwrap();
+    let now = ::std::time::UNIX_EPOCH.elapsed().unwrap_or_default();
     
     tai_unix(now.as_secs())
 }
 
-pub fn unpack(packed_tai: &[u8]) -> Tai {
+pub fn unpack(packed_tai: &[u8]) -> Result<Tai, TaiError> {
+    if packed_tai.len() < 8 {
+        return Err(TaiError::ShortInput { needed: 8, got: packed_tai.len() });
+    }
     let mut x: u64  = packed_tai[0] as u64;
     x <<= 8; x+= packed_tai[1] as u64;
     x <<= 8; x+= packed_tai[2] as u64;
@@ -41,14 +91,252 @@ pub fn unpack(packed_tai: &[u8]) -> Tai {
     x <<= 8; x+= packed_tai[5] as u64;
     x <<= 8; x+= packed_tai[6] as u64;
     x <<= 8; x+= packed_tai[7] as u64;
-    Tai(x)
+    Ok(Tai(x))
 }
 
 impl Tai {
  pub   fn as_secs(&self) -> u64 {
         self.0
     }
+
+    /// Packs into the 8-byte big-endian external TAI64 format.
+    pub fn pack(&self) -> [u8; 8] {
+        self.0.to_be_bytes()
+    }
+
+    pub fn checked_add(self, d: TaiDuration) -> Option<Tai> {
+        self.0.checked_add(d.0).map(Tai)
+    }
+
+    pub fn checked_sub(self, d: TaiDuration) -> Option<Tai> {
+        self.0.checked_sub(d.0).map(Tai)
+    }
+
+    pub fn saturating_add(self, d: TaiDuration) -> Tai {
+        Tai(self.0.saturating_add(d.0))
+    }
+
+    pub fn saturating_sub(self, d: TaiDuration) -> Tai {
+        Tai(self.0.saturating_sub(d.0))
+    }
+
+    /// The time from `earlier` to `self`, or `None` if `earlier` is later.
+    pub fn checked_duration_since(self, earlier: Tai) -> Option<TaiDuration> {
+        self.0.checked_sub(earlier.0).map(TaiDuration)
+    }
+
+    /// The time from `earlier` to `self`, or zero if `earlier` is later.
+    pub fn saturating_duration_since(self, earlier: Tai) -> TaiDuration {
+        TaiDuration(self.0.saturating_sub(earlier.0))
+    }
+
+    /// Converts to true TAI using the bundled leap-second table.
+    pub fn from_system_time(t: SystemTime) -> Tai {
+        Leapsecs::bundled().from_system_time(t).tai()
+    }
+
+    /// Converts to UTC using the bundled leap-second table.
+    pub fn to_system_time(&self) -> Result<SystemTime, TaiError> {
+        Leapsecs::bundled().to_system_time(Tain::from(*self))
+    }
+}
+
+impl From<SystemTime> for Tai {
+    fn from(t: SystemTime) -> Tai {
+        Tai::from_system_time(t)
+    }
+}
+
+impl TryFrom<Tai> for SystemTime {
+    type Error = TaiError;
+
+    fn try_from(t: Tai) -> Result<SystemTime, TaiError> {
+        t.to_system_time()
+    }
+}
+
+impl TaiDuration {
+    pub fn from_secs(secs: u64) -> TaiDuration {
+        TaiDuration(secs)
+    }
+
+    pub fn as_secs(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for TaiDuration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} seconds", self.0)
+    }
+}
+
+/// Truncates to whole seconds.
+impl From<Duration> for TaiDuration {
+    fn from(d: Duration) -> TaiDuration {
+        TaiDuration(d.as_secs())
+    }
+}
+
+impl From<TaiDuration> for Duration {
+    fn from(d: TaiDuration) -> Duration {
+        Duration::from_secs(d.0)
+    }
+}
+
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+/// A TAI64N label: a `Tai` second and a count of nanoseconds within it.
+#[derive(Debug, PartialEq, Copy, Clone, Eq, Ord, PartialOrd)]
+pub struct Tain {
+    sec: Tai,
+    nano: u32,
+}
+
+impl Tain {
+    /// Builds a label from a second and a nanosecond count, carrying whole
+    /// seconds out of `nano`.
+    pub fn new(sec: Tai, nano: u32) -> Tain {
+        Tain {
+            sec: Tai(sec.0.saturating_add((nano / NANOS_PER_SEC) as u64)),
+            nano: nano % NANOS_PER_SEC,
+        }
+    }
+
+    pub fn now() -> Tain {
+        let now = ::std::time::UNIX_EPOCH.elapsed().unwrap_or_default();
+
+        Tain::new(tai_unix(now.as_secs()), now.subsec_nanos())
+    }
+
+    /// Unpacks the 12-byte external TAI64N format: eight bytes of seconds
+    /// followed by four bytes of nanoseconds, both big-endian.
+    pub fn unpack(packed_tain: &[u8]) -> Result<Tain, TaiError> {
+        if packed_tain.len() < 12 {
+            return Err(TaiError::ShortInput { needed: 12, got: packed_tain.len() });
+        }
+        let mut nano: u32 = packed_tain[8] as u32;
+        nano <<= 8; nano += packed_tain[9] as u32;
+        nano <<= 8; nano += packed_tain[10] as u32;
+        nano <<= 8; nano += packed_tain[11] as u32;
+        Ok(Tain::new(unpack(&packed_tain[0..8])?, nano))
+    }
+
+    pub fn pack(&self) -> [u8; 12] {
+        let mut packed = [0; 12];
+        packed[0..8].copy_from_slice(&self.sec.pack());
+        packed[8..12].copy_from_slice(&self.nano.to_be_bytes());
+        packed
+    }
+
+    /// Converts to true TAI using the bundled leap-second table.
+    pub fn from_system_time(t: SystemTime) -> Tain {
+        Leapsecs::bundled().from_system_time(t)
+    }
+
+    /// Converts to UTC using the bundled leap-second table.
+    pub fn to_system_time(&self) -> Result<SystemTime, TaiError> {
+        Leapsecs::bundled().to_system_time(*self)
+    }
+
+    pub fn tai(&self) -> Tai {
+        self.sec
+    }
+
+    pub fn subsec_nanos(&self) -> u32 {
+        self.nano
+    }
+
+    /// The label as a `Duration` from the start of the TAI64 scale.
+    pub fn as_duration(&self) -> Duration {
+        Duration::new(self.sec.0, self.nano)
+    }
+
+    /// The time elapsed from `earlier` to `self`, or `None` if `earlier` is
+    /// later than `self`.
+    pub fn duration_since(&self, earlier: Tain) -> Option<Duration> {
+        if *self < earlier {
+            return None;
+        }
+        Some(self.as_duration() - earlier.as_duration())
+    }
+
+    fn as_nanos(&self) -> u128 {
+        self.sec.0 as u128 * NANOS_PER_SEC as u128 + self.nano as u128
+    }
+
+    fn from_nanos(nanos: u128) -> Tain {
+        let sec = nanos / NANOS_PER_SEC as u128;
+        if sec > u64::MAX as u128 {
+            return Tain { sec: Tai(u64::MAX), nano: NANOS_PER_SEC - 1 };
+        }
+        Tain {
+            sec: Tai(sec as u64),
+            nano: (nanos % NANOS_PER_SEC as u128) as u32,
+        }
+    }
+}
+
+impl From<Tai> for Tain {
+    fn from(sec: Tai) -> Tain {
+        Tain { sec, nano: 0 }
+    }
+}
+
+impl From<Tain> for Duration {
+    fn from(t: Tain) -> Duration {
+        t.as_duration()
+    }
+}
+
+impl From<SystemTime> for Tain {
+    fn from(t: SystemTime) -> Tain {
+        Tain::from_system_time(t)
+    }
+}
+
+impl TryFrom<Tain> for SystemTime {
+    type Error = TaiError;
+
+    fn try_from(t: Tain) -> Result<SystemTime, TaiError> {
+        t.to_system_time()
+    }
+}
+
+/// Adds `_rhs`, saturating at the largest label.
+impl ::std::ops::Add for Tain {
+    type Output = Tain;
+
+    fn add(self, _rhs: Tain) -> Tain {
+        Tain::from_nanos(self.as_nanos().saturating_add(_rhs.as_nanos()))
+    }
+}
+
+/// Subtracts `_rhs`, saturating at label zero.
+impl ::std::ops::Sub for Tain {
+    type Output = Tain;
+
+    fn sub(self, _rhs: Tain) -> Tain {
+        Tain::from_nanos(self.as_nanos().saturating_sub(_rhs.as_nanos()))
+    }
 }
+
+impl ::std::ops::Add<Duration> for Tain {
+    type Output = Tain;
+
+    fn add(self, _rhs: Duration) -> Tain {
+        Tain::from_nanos(self.as_nanos().saturating_add(_rhs.as_nanos()))
+    }
+}
+
+impl ::std::ops::Sub<Duration> for Tain {
+    type Output = Tain;
+
+    fn sub(self, _rhs: Duration) -> Tain {
+        Tain::from_nanos(self.as_nanos().saturating_sub(_rhs.as_nanos()))
+    }
+}
+
 #[test]
 fn tai_at_epoch() {
     assert_eq!(Tai(TAI_OFFSET), tai_unix(0));
@@ -73,13 +361,13 @@ fn sub_tais() {
     let t0 = Tai(12345);
     let t1 = Tai(12300);
 
-    assert_eq!(Tai(45), t0-t1);
+    assert_eq!(TaiDuration(45), t0-t1);
 }
 
 #[test]
 fn add_tais() {
     let t0 = Tai(123);
-    let t1 = Tai(123);
+    let t1 = TaiDuration(123);
 
     assert_eq!(Tai(246), t0+t1);
 }
@@ -94,18 +382,100 @@ fn tai_as_secs() {
 #[test]
 fn tai_unpack_zero() {
     let array: [u8; 8] = [0; 8];
-    assert_eq!(Tai(0), unpack(&array));
+    assert_eq!(Ok(Tai(0)), unpack(&array));
 }
 
 #[test]
 fn tai_unpack_one() {
     let array: [u8; 8] = [0,0,0,0,0,0,0,1];
-    assert_eq!(Tai(1), unpack(&array));
+    assert_eq!(Ok(Tai(1)), unpack(&array));
 }
 
 #[test]
 fn tai_unpack() {
     let tai_array: [u8; 8] = [ 0xff, 0, 0xff, 0, 0xff, 0, 0xff, 0 ];
     println!("{:?}", tai_array);
-    assert_eq!(Tai(0xff00ff00ff00ff00), unpack(&tai_array));
+    assert_eq!(Ok(Tai(0xff00ff00ff00ff00)), unpack(&tai_array));
+}
+
+#[test]
+fn tain_unpack() {
+    let tain_array: [u8; 12] = [0x40, 0, 0, 0, 0, 0, 0, 1, 0x3b, 0x9a, 0xc9, 0xff];
+    let t = Tain::unpack(&tain_array).unwrap();
+    assert_eq!(Tai(0x4000000000000001), t.tai());
+    assert_eq!(999_999_999, t.subsec_nanos());
+}
+
+#[test]
+fn tain_pack_round_trip() {
+    let tain_array: [u8; 12] = [0x40, 0, 0, 0, 0x58, 0x05, 0xa9, 0x12, 0, 0x0f, 0x42, 0x40];
+    assert_eq!(tain_array, Tain::unpack(&tain_array).unwrap().pack());
+}
+
+#[test]
+fn tain_carries_nanos() {
+    assert_eq!(Tain::new(Tai(2), 500), Tain::new(Tai(1), 1_000_000_500));
+}
+
+#[test]
+fn sub_tains_borrows() {
+    let t0 = Tain::new(Tai(10), 100);
+    let t1 = Tain::new(Tai(8), 900);
+
+    assert_eq!(Tain::new(Tai(1), 999_999_200), t0 - t1);
+    assert_eq!(Some(Duration::new(1, 999_999_200)), t0.duration_since(t1));
+    assert_eq!(None, t1.duration_since(t0));
+}
+
+#[test]
+fn add_duration_to_tain() {
+    let t0 = Tain::new(Tai(10), 999_000_000);
+
+    assert_eq!(Tain::new(Tai(11), 1_000_000), t0 + Duration::from_millis(2));
+    assert_eq!(Tain::new(Tai(10), 997_000_000), t0 - Duration::from_millis(2));
+}
+
+#[test]
+fn sub_later_tai_saturates() {
+    let t0 = Tai(12300);
+    let t1 = Tai(12345);
+
+    assert_eq!(TaiDuration(0), t0 - t1);
+    assert_eq!(None, t0.checked_duration_since(t1));
+    assert_eq!(Some(TaiDuration(45)), t1.checked_duration_since(t0));
+}
+
+#[test]
+fn checked_tai_arithmetic() {
+    assert_eq!(None, Tai(u64::MAX).checked_add(TaiDuration(1)));
+    assert_eq!(Tai(u64::MAX), Tai(u64::MAX) + TaiDuration(1));
+    assert_eq!(None, Tai(0).checked_sub(TaiDuration(1)));
+    assert_eq!(Tai(0), Tai(0) - TaiDuration(1));
+}
+
+#[test]
+fn tai_unpack_short() {
+    assert_eq!(Err(TaiError::ShortInput { needed: 8, got: 3 }), unpack(&[0, 0, 0]));
+    assert_eq!(Err(TaiError::ShortInput { needed: 12, got: 8 }), Tain::unpack(&[0; 8]));
+}
+
+#[test]
+fn tai_pack_round_trip() {
+    let tai_array: [u8; 8] = [ 0xff, 0, 0xff, 0, 0xff, 0, 0xff, 0 ];
+    assert_eq!(tai_array, unpack(&tai_array).unwrap().pack());
+}
+
+#[test]
+fn tai_system_time_conversions() {
+    let t = ::std::time::UNIX_EPOCH + Duration::from_secs(1500000000);
+    let tai = Tai::from(t);
+    assert_eq!(Ok(t), SystemTime::try_from(tai));
+    assert_eq!(Err(TaiError::OutOfRange), SystemTime::try_from(Tai(u64::MAX)));
+}
+
+#[test]
+fn tai_duration_conversions() {
+    assert_eq!(TaiDuration(3), TaiDuration::from(Duration::from_millis(3500)));
+    assert_eq!(Duration::from_secs(3), Duration::from(TaiDuration(3)));
+    assert_eq!("3 seconds", TaiDuration(3).to_string());
 }
@@ -0,0 +1,91 @@
+//! Fixtures shared by the unit tests.
+
+use std::ffi::CString;
+use std::fs::{self, File, OpenOptions};
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use libc;
+
+use status::{Readiness, RunState, ServiceStatus, StatusFormat, SvWants};
+use tai::Tain;
+
+/// A daemontools status that wants the service up: running as `pid`, or
+/// down if there is none. Tests change other fields with `..status(pid)`.
+pub(crate) fn status(pid: Option<u32>) -> ServiceStatus {
+    ServiceStatus {
+        pid,
+        normally_up: true,
+        is_paused: false,
+        duration: 0,
+        since: Tain::now(),
+        wants: Some(SvWants::WantsUp),
+        format: StatusFormat::Daemontools,
+        run_state: if pid.is_some() { RunState::Run } else { RunState::Down },
+        got_term: false,
+        last_exit: None,
+        readiness: Readiness::Unknown,
+    }
+}
+
+/// Creates a service directory with `supervise/ok` and `supervise/control`
+/// FIFOs but no status, and nobody reading the FIFOs.
+pub(crate) fn fake_service(name: &str) -> PathBuf {
+    let path = ::std::env::temp_dir()
+        .join(format!("rupervise-test-{}-{}", name, ::std::process::id()));
+    fs::create_dir_all(path.join("supervise")).unwrap();
+    for fifo in &["supervise/ok", "supervise/control"] {
+        let fifo = CString::new(path.join(fifo).as_os_str().as_bytes()).unwrap();
+        assert_eq!(0, unsafe { libc::mkfifo(fifo.as_ptr(), 0o600) });
+    }
+    path
+}
+
+/// Opens a FIFO under `path` for reading without blocking, as a running
+/// supervisor holds it.
+pub(crate) fn open_fifo(path: &Path, name: &str) -> File {
+    OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path.join(name))
+        .unwrap()
+}
+
+/// Writes a daemontools status file the way `supervise` does: up as `pid`,
+/// or down if it is 0.
+pub(crate) fn write_status(path: &Path, pid: u32) {
+    let mut status = [0u8; 18];
+    status[0..12].copy_from_slice(&Tain::now().pack());
+    status[12..16].copy_from_slice(&pid.to_le_bytes());
+    status[17] = b'u';
+    fs::write(path.join("supervise/status.new"), &status[..]).unwrap();
+    fs::rename(path.join("supervise/status.new"), path.join("supervise/status")).unwrap();
+}
+
+/// Plays the supervisor of the service at `path` on a thread: reads control
+/// commands until it gets `last`, then runs `then`. The thread returns every
+/// byte it read.
+pub(crate) fn fake_supervise<F>(path: &Path, last: u8, then: F) -> thread::JoinHandle<Vec<u8>>
+    where F: FnOnce() + Send + 'static
+{
+    // Opened for writing too, so reads block until there is something to
+    // read rather than finding no writer.
+    let mut control = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path.join("supervise/control"))
+        .unwrap();
+    thread::spawn(move || {
+        let mut sent = Vec::new();
+        let mut buf = [0u8; 8];
+        while !sent.contains(&last) {
+            let n = control.read(&mut buf).unwrap();
+            sent.extend_from_slice(&buf[..n]);
+        }
+        then();
+        sent
+    })
+}
@@ -0,0 +1,221 @@
+//! Blocking until services reach a state, like `svwait` or `s6-svwait`.
+//!
+//! Status files are watched with inotify and only re-read when the
+//! supervisor rewrites them. Where inotify is not available, or a service
+//! has no `supervise` directory yet, the statuses are polled instead.
+
+use std::cmp;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libc;
+
+use inotify::Inotify;
+use status::{update_supervise, Service, SvstatType};
+
+/// How often statuses are re-read when they cannot be watched.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// supervise, runsv and s6-supervise all write `status.new` and rename it
+/// over `status`; the others catch a status written in place.
+const WATCH_MASK: u32 = libc::IN_MOVED_TO | libc::IN_CLOSE_WRITE | libc::IN_CREATE;
+
+/// The state to wait for.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Condition {
+    Up,
+    Down,
+    /// Up, and for at least this long.
+    UpFor(Duration),
+    /// Up with a different pid than when the wait started. A restart asked
+    /// for just before the wait may already be over by then; use
+    /// `PidChangedFrom` with the pid from before asking.
+    PidChanged,
+    /// Up with a pid other than this one, `None` meaning it was down.
+    PidChangedFrom(Option<u32>),
+}
+
+/// Whether every service or any one of them has to reach the condition.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mode {
+    All,
+    Any,
+}
+
+/// The timeout expired first. Holds the last status read of each service.
+#[derive(Debug)]
+pub struct TimedOut {
+    pub services: Vec<Service>,
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "timed out waiting for services")
+    }
+}
+
+impl Error for TimedOut {}
+
+struct Watched {
+    service: Service,
+    initial_pid: Option<u32>,
+}
+
+impl Condition {
+    /// How long until `status` meets the condition if nothing else changes:
+    /// zero if it already does, `None` if it never will.
+    fn remaining(self, initial_pid: Option<u32>, status: &SvstatType) -> Option<Duration> {
+        let st = match *status {
+            SvstatType::SvOk(ref st) => st,
+            SvstatType::SvError(_) => return None,
+        };
+        let met = match self {
+            Condition::Up => st.is_up(),
+            Condition::Down => !st.is_up(),
+            Condition::UpFor(min) => {
+                return if st.is_up() {
+                    Some(min.saturating_sub(Duration::from_secs(st.duration)))
+                } else {
+                    None
+                };
+            }
+            Condition::PidChanged => st.is_up() && st.pid != initial_pid,
+            Condition::PidChangedFrom(pid) => st.is_up() && st.pid != pid,
+        };
+        if met { Some(Duration::from_secs(0)) } else { None }
+    }
+}
+
+/// Blocks until the services at `paths` meet `condition`, as required by
+/// `mode`, or `timeout` expires.
+///
+/// Returns the services with the statuses that met the condition.
+pub fn wait<P: AsRef<Path>>(paths: &[P],
+                            condition: Condition,
+                            mode: Mode,
+                            timeout: Duration)
+                            -> Result<Vec<Service>, TimedOut> {
+    let deadline = Instant::now() + timeout;
+
+    let mut notify = Inotify::new().ok();
+    let mut polling = notify.is_none();
+    let mut watched: Vec<Watched> = Vec::new();
+    for path in paths {
+        let path = path.as_ref();
+        if let Some(ref n) = notify {
+            polling |= n.add_watch(&path.join("supervise"), WATCH_MASK).is_err();
+        }
+        let mut service = Service::new(path);
+        update_supervise(&mut service);
+        let initial_pid = match service.status {
+            Some(SvstatType::SvOk(ref st)) if st.is_up() => st.pid,
+            _ => None,
+        };
+        watched.push(Watched { service, initial_pid });
+    }
+
+    let mut first = true;
+    loop {
+        let mut met = 0;
+        let mut wake: Option<Duration> = None;
+        for w in &mut watched {
+            if !first {
+                update_supervise(&mut w.service);
+            }
+            let status = w.service.status.as_ref().expect("status was read");
+            match condition.remaining(w.initial_pid, status) {
+                Some(d) if d == Duration::from_secs(0) => met += 1,
+                // Round up to whole seconds, since that is all the status
+                // duration shows.
+                Some(d) => wake = Some(cmp::min(wake.unwrap_or(d), d + Duration::from_secs(1))),
+                None => {}
+            }
+        }
+        first = false;
+
+        let done = match mode {
+            Mode::All => met == watched.len(),
+            Mode::Any => met > 0,
+        };
+        if done {
+            return Ok(watched.into_iter().map(|w| w.service).collect());
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(TimedOut { services: watched.into_iter().map(|w| w.service).collect() });
+        }
+        let mut sleep = deadline - now;
+        if let Some(d) = wake {
+            sleep = cmp::min(sleep, d);
+        }
+        if polling {
+            sleep = cmp::min(sleep, POLL_INTERVAL);
+        }
+
+        let watching = match notify {
+            Some(ref mut n) if !polling => n.wait_for(b"status", sleep).is_ok(),
+            _ => false,
+        };
+        if !watching {
+            if !polling {
+                // inotify failed mid-wait; carry on without it.
+                polling = true;
+                notify = None;
+            }
+            thread::sleep(sleep);
+        }
+    }
+}
+
+#[test]
+fn wakes_on_status_rename() {
+    use testing::{fake_service, open_fifo, write_status};
+
+    let path = fake_service("up");
+    let _ok = open_fifo(&path, "supervise/ok");
+    write_status(&path, 0);
+
+    let writer = {
+        let path = path.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            write_status(&path, 1234);
+        })
+    };
+    let started = Instant::now();
+    let services = wait(&[&path], Condition::PidChanged, Mode::All, Duration::from_secs(5))
+        .unwrap();
+    writer.join().unwrap();
+
+    assert!(started.elapsed() < POLL_INTERVAL);
+    match services[0].status {
+        Some(SvstatType::SvOk(ref st)) => assert_eq!(Some(1234), st.pid),
+        ref other => panic!("unexpected status {:?}", other),
+    }
+    ::std::fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn all_and_any() {
+    use testing::{fake_service, open_fifo, write_status};
+
+    let up = fake_service("all-up");
+    let down = fake_service("all-down");
+    let _ok = (open_fifo(&up, "supervise/ok"), open_fifo(&down, "supervise/ok"));
+    write_status(&up, 1234);
+    write_status(&down, 0);
+
+    assert!(wait(&[&up, &down], Condition::Up, Mode::Any, Duration::from_secs(0)).is_ok());
+    let e = wait(&[&up, &down], Condition::Up, Mode::All, Duration::from_millis(50))
+        .unwrap_err();
+    assert_eq!(2, e.services.len());
+    assert!(wait(&[&up], Condition::UpFor(Duration::from_secs(3600)),
+                 Mode::All, Duration::from_millis(50)).is_err());
+
+    ::std::fs::remove_dir_all(&up).unwrap();
+    ::std::fs::remove_dir_all(&down).unwrap();
+}
@@ -0,0 +1,118 @@
+//! Readiness checks run on a few threads of their own, so that a slow
+//! `check` script holds up only its own service and never the events of
+//! the others.
+
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use status::{Readiness, ServiceDir, ServiceStatus};
+
+/// How many checks may run at once.
+const WORKERS: usize = 4;
+
+/// A check of the service at `path`, `index` in the watcher's list, as it
+/// was when the check was asked for.
+struct Job {
+    index: usize,
+    path: PathBuf,
+    status: ServiceStatus,
+}
+
+/// A pool of threads checking readiness, started on the first check.
+pub(super) struct Checks {
+    jobs: Option<mpsc::Sender<Job>>,
+    done_tx: mpsc::Sender<(usize, ServiceStatus)>,
+    done: mpsc::Receiver<(usize, ServiceStatus)>,
+    /// Whether each service has a check queued or running.
+    pending: Vec<bool>,
+}
+
+impl Checks {
+    pub(super) fn new(services: usize) -> Checks {
+        let (done_tx, done) = mpsc::channel();
+        Checks {
+            jobs: None,
+            done_tx,
+            done,
+            pending: vec![false; services],
+        }
+    }
+
+    /// Queues a check of service `index`, unless one is already queued or
+    /// running.
+    pub(super) fn start(&mut self, index: usize, path: &Path, status: &ServiceStatus) {
+        if self.pending[index] {
+            return;
+        }
+        let job = Job {
+            index,
+            path: path.to_path_buf(),
+            status: ServiceStatus { readiness: Readiness::Unknown, ..status.clone() },
+        };
+        if self.jobs.is_none() {
+            self.jobs = Some(self.spawn_workers());
+        }
+        if let Some(ref jobs) = self.jobs {
+            self.pending[index] = jobs.send(job).is_ok();
+        }
+    }
+
+    /// Whether any check is queued or running.
+    pub(super) fn is_busy(&self) -> bool {
+        self.pending.iter().any(|&p| p)
+    }
+
+    /// The checks that have finished since the last call: each service's
+    /// index and its status as checked.
+    pub(super) fn finished(&mut self) -> Vec<(usize, ServiceStatus)> {
+        let finished: Vec<_> = self.done.try_iter().collect();
+        for &(index, _) in &finished {
+            self.pending[index] = false;
+        }
+        finished
+    }
+
+    /// Blocks until every check queued so far has finished, and returns
+    /// them all.
+    pub(super) fn wait(&mut self) -> Vec<(usize, ServiceStatus)> {
+        let mut finished = Vec::new();
+        while self.is_busy() {
+            match self.done.recv() {
+                Ok((index, status)) => {
+                    self.pending[index] = false;
+                    finished.push((index, status));
+                }
+                Err(_) => break,
+            }
+        }
+        finished
+    }
+
+    fn spawn_workers(&self) -> mpsc::Sender<Job> {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+        for _ in 0..WORKERS {
+            let queue = queue.clone();
+            let done = self.done_tx.clone();
+            thread::spawn(move || loop {
+                // The lock is held only to take a job, never while one runs.
+                let job = match queue.lock() {
+                    Ok(queue) => queue.recv(),
+                    Err(_) => return,
+                };
+                let Job { index, path, mut status } = match job {
+                    Ok(job) => job,
+                    Err(_) => return,
+                };
+                if let Ok(dir) = ServiceDir::open(&path) {
+                    status.check_readiness(&dir);
+                }
+                if done.send((index, status)).is_err() {
+                    return;
+                }
+            });
+        }
+        jobs
+    }
+}
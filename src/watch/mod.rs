@@ -0,0 +1,572 @@
+//! Following many services at once, re-reading a service only when its
+//! supervisor rewrites `supervise/status` or its `down` file comes or goes.
+//! If the kernel's event queue overflows, every service is re-read.
+//!
+//! Services that cannot be watched with inotify, because the filesystem
+//! does not support it or the `supervise` directory does not exist yet,
+//! are polled every few seconds instead, as are up services that are not
+//! ready yet by their `ready-file` or `check` script.
+//!
+//! Readiness is checked on a small pool of threads, once each time a
+//! service comes up and again on each poll while it is not ready. A change
+//! is reported at once and its readiness in a later event, when the check
+//! is done.
+
+mod checks;
+
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libc;
+
+use inotify::{self, Inotify};
+use self::checks::Checks;
+use status::{transitions, update_supervise, Readiness, Service, ServiceStatus, StatusFormat,
+             SvstatType, Transition};
+use tai::Tain;
+
+/// How often services that are not watched are re-read.
+const SLOW_POLL: Duration = Duration::from_secs(5);
+/// How often finished readiness checks are looked for while any run.
+const CHECK_POLL: Duration = Duration::from_millis(50);
+
+/// `status` is replaced by a rename; `ok` (or s6's `control`) is closed by
+/// the supervisor when it exits.
+const SUPERVISE_MASK: u32 = libc::IN_MOVED_TO | libc::IN_CLOSE_WRITE | libc::IN_CREATE |
+                            libc::IN_CLOSE_NOWRITE;
+const SERVICE_MASK: u32 = libc::IN_CREATE | libc::IN_DELETE | libc::IN_MOVED_TO |
+                          libc::IN_MOVED_FROM;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Watched {
+    /// The service directory, for its `down` file.
+    ServiceDir,
+    /// Its `supervise` directory.
+    SuperviseDir,
+}
+
+/// A service changing state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// Where the service is in the watcher's list.
+    pub index: usize,
+    pub service: PathBuf,
+    /// The status before the change; `None` only if it had never been read.
+    pub old: Option<SvstatType>,
+    pub new: SvstatType,
+    /// When the change was seen.
+    pub at: Tain,
+}
+
+impl Event {
+    pub fn transitions(&self) -> Vec<Transition> {
+        match self.old {
+            Some(ref old) => transitions(old, &self.new),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    /// Formats the event as a `tai64n` stamped log line, such as
+    /// `@4000000058060a2b00000000 /service/foo: down (was pid 123)`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}: ", self.at, self.service.display())?;
+        let changes = self.transitions();
+        if changes.is_empty() {
+            return match self.new {
+                SvstatType::SvError(ref e) => write!(f, "{}", e),
+                SvstatType::SvOk(_) => write!(f, "changed"),
+            };
+        }
+        for (n, t) in changes.iter().enumerate() {
+            if n > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", t)?;
+        }
+        Ok(())
+    }
+}
+
+/// Follows a set of services and reports which of them changed state.
+///
+/// Changes are pulled with [`Watcher::next_events`], as an iterator with
+/// [`Watcher::into_events`], or from a channel fed by a thread with
+/// [`Watcher::spawn`].
+pub struct Watcher {
+    services: Vec<Service>,
+    notify: Option<Inotify>,
+    /// The service and directory each watch descriptor is for.
+    watches: HashMap<i32, (usize, Watched)>,
+    /// Whether each service's directory and its `supervise` directory are
+    /// both being watched.
+    watched: Vec<bool>,
+    next_poll: Instant,
+    checks: Checks,
+}
+
+impl Watcher {
+    /// Starts watching `services` and reads each of them once, checking
+    /// the readiness of those that are up.
+    pub fn new(services: Vec<Service>) -> Watcher {
+        let mut watcher = Watcher {
+            watched: vec![false; services.len()],
+            checks: Checks::new(services.len()),
+            services,
+            notify: Inotify::new().ok(),
+            watches: HashMap::new(),
+            next_poll: Instant::now() + SLOW_POLL,
+        };
+        for i in 0..watcher.services.len() {
+            watcher.watch(i);
+            watcher.refresh(i);
+        }
+        for (i, checked) in watcher.checks.wait() {
+            watcher.finish_check(i, checked);
+        }
+        watcher
+    }
+
+    pub fn services(&self) -> &[Service] {
+        &self.services
+    }
+
+    /// Whether any service has to be polled for lack of a watch.
+    pub fn is_polling(&self) -> bool {
+        self.watched.iter().any(|&w| !w)
+    }
+
+    /// Blocks until at least one service changes state, and returns the
+    /// indices of those that did.
+    pub fn next_changes(&mut self) -> Vec<usize> {
+        self.next_events().into_iter().map(|e| e.index).collect()
+    }
+
+    /// Blocks until at least one service changes state, and returns an event
+    /// for each that did.
+    ///
+    /// A supervisor rewriting the status without changing anything, or time
+    /// passing, does not count as a change.
+    pub fn next_events(&mut self) -> Vec<Event> {
+        loop {
+            let mut stale: Vec<usize> = Vec::new();
+            let mut timeout = self.next_poll.saturating_duration_since(Instant::now());
+            if self.checks.is_busy() {
+                timeout = cmp::min(timeout, CHECK_POLL);
+            }
+
+            let events = match self.notify {
+                Some(ref mut n) => n.wait(timeout),
+                None => {
+                    thread::sleep(timeout);
+                    Ok(Vec::new())
+                }
+            };
+            match events {
+                Ok(events) => {
+                    for e in events {
+                        if e.mask & libc::IN_Q_OVERFLOW != 0 {
+                            // Events were dropped, so any service may have
+                            // changed.
+                            stale.extend(0..self.services.len());
+                        } else if let Some(i) = self.relevant(&e) {
+                            stale.push(i);
+                        }
+                    }
+                }
+                Err(_) => {
+                    // Carry on polling everything.
+                    self.notify = None;
+                    self.watches.clear();
+                    self.watched = vec![false; self.services.len()];
+                }
+            }
+
+            if Instant::now() >= self.next_poll {
+                for i in 0..self.services.len() {
+                    if !self.watched[i] {
+                        self.watch(i);
+                        stale.push(i);
+                    } else if self.awaits_readiness(i) {
+                        stale.push(i);
+                    }
+                }
+                self.next_poll = Instant::now() + SLOW_POLL;
+            }
+
+            let mut events = Vec::new();
+            for (i, checked) in self.checks.finished() {
+                events.extend(self.finish_check(i, checked));
+            }
+            stale.sort();
+            stale.dedup();
+            events.extend(stale.into_iter().filter_map(|i| self.refresh(i)));
+            if !events.is_empty() {
+                return events;
+            }
+        }
+    }
+
+    fn watch(&mut self, i: usize) {
+        let notify = match self.notify {
+            Some(ref n) => n,
+            None => return,
+        };
+        let dir = &self.services[i].name;
+        let service = notify.add_watch(dir, SERVICE_MASK);
+        let supervise = notify.add_watch(&dir.join("supervise"), SUPERVISE_MASK);
+        if let Ok(wd) = service {
+            self.watches.insert(wd, (i, Watched::ServiceDir));
+        }
+        if let Ok(wd) = supervise {
+            self.watches.insert(wd, (i, Watched::SuperviseDir));
+        }
+        self.watched[i] = service.is_ok() && supervise.is_ok();
+    }
+
+    /// Whether service `i` is up but not yet ready by checks that nothing
+    /// tells us about, unlike s6's readiness, which comes with its status.
+    fn awaits_readiness(&self, i: usize) -> bool {
+        match self.services[i].status {
+            Some(SvstatType::SvOk(ref st)) => {
+                st.is_up() && st.readiness == Readiness::NotReady && st.format != StatusFormat::S6
+            }
+            _ => false,
+        }
+    }
+
+    /// The service `e` says may have changed, if any.
+    fn relevant(&mut self, e: &inotify::Event) -> Option<usize> {
+        let (i, which) = *self.watches.get(&e.wd)?;
+        if e.mask & libc::IN_IGNORED != 0 {
+            // The directory went away; poll until both are watched again.
+            self.watches.remove(&e.wd);
+            self.watched[i] = false;
+            return Some(i);
+        }
+        let name = e.name.as_slice();
+        if which == Watched::ServiceDir && name == b"supervise" && !self.watched[i] {
+            // The supervisor has just been started for the first time.
+            self.watch(i);
+            return Some(i);
+        }
+        let hit = match which {
+            Watched::ServiceDir => name == b"down",
+            Watched::SuperviseDir if e.mask & libc::IN_CLOSE_NOWRITE != 0 => {
+                name == b"ok" || name == b"control"
+            }
+            Watched::SuperviseDir => name == b"status",
+        };
+        if hit { Some(i) } else { None }
+    }
+
+    /// Re-reads service `i`, returning an event if its state changed.
+    ///
+    /// A service that is still on the same run keeps the readiness last
+    /// checked. One that has just come up, or is still not ready, has its
+    /// readiness checked again; the result comes from [`Watcher::finish_check`].
+    fn refresh(&mut self, i: usize) -> Option<Event> {
+        let old = self.services[i].status.clone();
+        let service = update_supervise(&mut self.services[i]);
+        if let Some(SvstatType::SvOk(ref mut st)) = service.status {
+            if st.is_up() && st.format != StatusFormat::S6 {
+                let same = match old {
+                    Some(SvstatType::SvOk(ref o)) if same_run(o, st) => Some(o.readiness),
+                    _ => None,
+                };
+                if let Some(readiness) = same {
+                    st.readiness = readiness;
+                }
+                if same.is_none() || st.readiness == Readiness::NotReady {
+                    self.checks.start(i, &service.name, st);
+                }
+            }
+        }
+        self.event(i, old)
+    }
+
+    /// Takes the readiness of service `i` from a check of it, unless the
+    /// service has moved on to another run since. Returns an event if that
+    /// changed its state.
+    fn finish_check(&mut self, i: usize, checked: ServiceStatus) -> Option<Event> {
+        let old = self.services[i].status.clone();
+        match self.services[i].status {
+            Some(SvstatType::SvOk(ref mut st)) if same_run(st, &checked) => {
+                st.readiness = checked.readiness;
+            }
+            _ => return None,
+        }
+        self.event(i, old)
+    }
+
+    /// An event for service `i`, if its state is no longer that of `old`.
+    fn event(&self, i: usize, old: Option<SvstatType>) -> Option<Event> {
+        let new = self.services[i].status.clone().expect("status was read");
+        let changed = match (&old, &new) {
+            (Some(SvstatType::SvOk(o)), SvstatType::SvOk(n)) => !same_state(o, n),
+            (old, new) => old.as_ref() != Some(new),
+        };
+        if !changed {
+            return None;
+        }
+        Some(Event {
+            index: i,
+            service: self.services[i].name.clone(),
+            old,
+            new,
+            at: Tain::now(),
+        })
+    }
+
+    /// Turns the watcher into an endless iterator of events.
+    pub fn into_events(self) -> Events {
+        Events {
+            watcher: self,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Watches on a thread of its own, sending events down the returned
+    /// channel. The thread stops once the receiver is dropped and the next
+    /// change comes in.
+    pub fn spawn(self) -> mpsc::Receiver<Event> {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for event in self.into_events() {
+                if tx.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+        rx
+    }
+}
+
+/// The events of a [`Watcher`], one at a time.
+pub struct Events {
+    watcher: Watcher,
+    pending: VecDeque<Event>,
+}
+
+impl Events {
+    pub fn watcher(&self) -> &Watcher {
+        &self.watcher
+    }
+}
+
+impl Iterator for Events {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        while self.pending.is_empty() {
+            self.pending.extend(self.watcher.next_events());
+        }
+        self.pending.pop_front()
+    }
+}
+
+/// Whether two statuses are of the same run of the service.
+fn same_run(a: &ServiceStatus, b: &ServiceStatus) -> bool {
+    a.pid == b.pid && a.since == b.since
+}
+
+/// Whether two statuses differ only in how long ago they were taken.
+fn same_state(a: &ServiceStatus, b: &ServiceStatus) -> bool {
+    fn ready(r: Readiness) -> Readiness {
+        match r {
+            Readiness::Ready(_) => Readiness::Ready(None),
+            other => other,
+        }
+    }
+    a.pid == b.pid && a.normally_up == b.normally_up && a.is_paused == b.is_paused &&
+    a.since == b.since && a.wants == b.wants && a.run_state == b.run_state &&
+    a.got_term == b.got_term && a.last_exit == b.last_exit &&
+    ready(a.readiness) == ready(b.readiness)
+}
+
+#[test]
+fn reports_only_the_changed_service() {
+    use std::fs;
+    use testing::{fake_service, open_fifo, write_status};
+
+    let quiet = fake_service("quiet");
+    let busy = fake_service("busy");
+    let mut fifos = Vec::new();
+    for path in &[&quiet, &busy] {
+        fifos.push(open_fifo(path, "supervise/ok"));
+        write_status(path, 100);
+    }
+
+    let mut watcher = Watcher::new(vec![Service::new(&quiet), Service::new(&busy)]);
+    assert!(!watcher.is_polling());
+
+    let writer = {
+        let busy = busy.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            write_status(&busy, 200);
+        })
+    };
+    let started = Instant::now();
+    assert_eq!(vec![1], watcher.next_changes());
+    assert!(started.elapsed() < SLOW_POLL);
+    writer.join().unwrap();
+    assert_eq!(1, watcher.services()[1].history.count_within(SLOW_POLL, Instant::now()));
+
+    fs::write(quiet.join("down"), "").unwrap();
+    assert_eq!(vec![0], watcher.next_changes());
+
+    let events = watcher.spawn();
+    write_status(&busy, 300);
+    let event = events.recv_timeout(SLOW_POLL).unwrap();
+    assert_eq!(busy, event.service);
+    assert_eq!(vec![Transition::Restarted { old_pid: 200, new_pid: 300 }], event.transitions());
+
+    fs::remove_dir_all(&quiet).unwrap();
+    fs::remove_dir_all(&busy).unwrap();
+}
+
+#[test]
+fn polls_for_readiness() {
+    use std::fs;
+    use testing::{fake_service, open_fifo, write_status};
+
+    let path = fake_service("ready");
+    let _ok = open_fifo(&path, "supervise/ok");
+    fs::write(path.join("ready-file"), "ready\n").unwrap();
+    write_status(&path, 100);
+
+    let mut watcher = Watcher::new(vec![Service::new(&path)]);
+    assert!(watcher.awaits_readiness(0));
+
+    fs::write(path.join("ready"), "").unwrap();
+    watcher.next_poll = Instant::now();
+    let events = watcher.next_events();
+    assert_eq!(1, events.len());
+    match events[0].new {
+        SvstatType::SvOk(ref st) => assert_eq!(Readiness::Ready(Some(0)), st.readiness),
+        ref other => panic!("unexpected status {:?}", other),
+    }
+    assert!(!watcher.awaits_readiness(0));
+    fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn rereads_every_service_after_an_overflow() {
+    use std::fs;
+    use testing::{fake_service, open_fifo, write_status};
+
+    let quiet = fake_service("overflow-quiet");
+    let busy = fake_service("overflow-busy");
+    let mut fifos = Vec::new();
+    for path in &[&quiet, &busy] {
+        fifos.push(open_fifo(path, "supervise/ok"));
+        write_status(path, 100);
+    }
+    let watcher = Watcher::new(vec![Service::new(&quiet), Service::new(&busy)]);
+
+    // Each rename is an event: enough to fill the queue, so that the status
+    // change after them is lost.
+    let limit: usize = fs::read_to_string("/proc/sys/fs/inotify/max_queued_events")
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap();
+    let (a, b) = (busy.join("supervise/junk.a"), busy.join("supervise/junk.b"));
+    fs::write(&a, "").unwrap();
+    for _ in 0..limit / 2 + 1 {
+        fs::rename(&a, &b).unwrap();
+        fs::rename(&b, &a).unwrap();
+    }
+    write_status(&quiet, 200);
+
+    let event = watcher.spawn().recv_timeout(2 * SLOW_POLL).unwrap();
+    assert_eq!(quiet, event.service);
+    assert_eq!(vec![Transition::Restarted { old_pid: 100, new_pid: 200 }], event.transitions());
+    fs::remove_dir_all(&quiet).unwrap();
+    fs::remove_dir_all(&busy).unwrap();
+}
+
+#[test]
+fn rewatches_the_service_dir() {
+    use std::fs;
+    use testing::{fake_service, open_fifo, write_status};
+
+    let path = fake_service("rewatch");
+    let _ok = open_fifo(&path, "supervise/ok");
+    write_status(&path, 100);
+    let mut watcher = Watcher::new(vec![Service::new(&path)]);
+
+    let wd = watcher.watches
+        .iter()
+        .find(|&(_, &(_, which))| which == Watched::ServiceDir)
+        .map(|(&wd, _)| wd)
+        .unwrap();
+    let ignored = inotify::Event {
+        wd,
+        mask: libc::IN_IGNORED,
+        name: Vec::new(),
+    };
+    assert_eq!(Some(0), watcher.relevant(&ignored));
+    assert!(watcher.is_polling());
+
+    watcher.next_poll = Instant::now();
+    let events = watcher.spawn();
+    let writer = {
+        let path = path.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::write(path.join("down"), "").unwrap();
+        })
+    };
+    let event = events.recv_timeout(SLOW_POLL / 2).unwrap();
+    writer.join().unwrap();
+    match event.new {
+        SvstatType::SvOk(ref st) => assert!(!st.normally_up),
+        ref other => panic!("unexpected status {:?}", other),
+    }
+    fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn slow_checks_do_not_hold_up_events() {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use testing::{fake_service, open_fifo, write_status};
+
+    let slow = fake_service("slow-check");
+    let busy = fake_service("slow-busy");
+    let mut fifos = Vec::new();
+    for path in &[&slow, &busy] {
+        fifos.push(open_fifo(path, "supervise/ok"));
+        write_status(path, 100);
+    }
+    // Not ready, and slow to say so once `slow` exists.
+    fs::write(slow.join("check"), "#!/bin/sh\n[ -e slow ] && sleep 3\nexit 1\n").unwrap();
+    fs::set_permissions(slow.join("check"), fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut watcher = Watcher::new(vec![Service::new(&slow), Service::new(&busy)]);
+    assert!(watcher.awaits_readiness(0));
+
+    fs::write(slow.join("slow"), "").unwrap();
+    watcher.next_poll = Instant::now();
+    let writer = {
+        let busy = busy.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            write_status(&busy, 200);
+        })
+    };
+    let started = Instant::now();
+    assert_eq!(vec![1], watcher.next_changes());
+    assert!(started.elapsed() < Duration::from_secs(2));
+    writer.join().unwrap();
+    fs::remove_dir_all(&slow).unwrap();
+    fs::remove_dir_all(&busy).unwrap();
+}
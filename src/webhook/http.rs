@@ -0,0 +1,113 @@
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// An `http://` endpoint. TLS is not supported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Url {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseUrlError(String);
+
+impl fmt::Display for ParseUrlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bad webhook URL {:?}: expected http://host[:port]/path", self.0)
+    }
+}
+
+impl ::std::error::Error for ParseUrlError {}
+
+impl FromStr for Url {
+    type Err = ParseUrlError;
+
+    fn from_str(s: &str) -> Result<Url, ParseUrlError> {
+        let bad = || ParseUrlError(s.to_string());
+        let rest = s.strip_prefix("http://").ok_or_else(bad)?;
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rfind(':') {
+            Some(colon) => (&authority[..colon], authority[colon + 1..].parse().map_err(|_| bad())?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(bad());
+        }
+        Ok(Url {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "http://{}", self.host)?;
+        if self.port != 80 {
+            write!(f, ":{}", self.port)?;
+        }
+        write!(f, "{}", self.path)
+    }
+}
+
+/// POSTs `body` as JSON to `url` and returns the response status code.
+pub fn post(url: &Url, body: &[u8], timeout: Duration) -> io::Result<u16> {
+    let addr = (url.host.as_str(), url.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "host has no address"))?;
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let host = if url.port == 80 {
+        url.host.clone()
+    } else {
+        format!("{}:{}", url.host, url.port)
+    };
+    write!(stream,
+           "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: rupervise\r\n\
+            Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+           url.path,
+           host,
+           body.len())?;
+    stream.write_all(body)?;
+
+    // Only the status line matters: "HTTP/1.1 200 OK".
+    let mut response = Vec::new();
+    let mut buf = [0u8; 256];
+    while !response.contains(&b'\n') {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        response.extend_from_slice(&buf[..n]);
+    }
+    let line = String::from_utf8_lossy(&response);
+    line.split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bad HTTP response"))
+}
+
+#[test]
+fn parse_urls() {
+    let url: Url = "http://hooks.example.com:8080/rupervise?x=1".parse().unwrap();
+    assert_eq!("hooks.example.com", url.host);
+    assert_eq!(8080, url.port);
+    assert_eq!("/rupervise?x=1", url.path);
+    assert_eq!("http://hooks.example.com:8080/rupervise?x=1", url.to_string());
+
+    assert_eq!("http://example.com/", "http://example.com".parse::<Url>().unwrap().to_string());
+    assert!("https://example.com/".parse::<Url>().is_err());
+    assert!("http://:80/".parse::<Url>().is_err());
+}
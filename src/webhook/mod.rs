@@ -0,0 +1,386 @@
+//! POSTing service changes to HTTP endpoints as JSON.
+//!
+//! Each notification is written to a spool directory, one file per
+//! endpoint, before it is sent, and removed once the endpoint accepts it,
+//! so nothing is lost when rupervise restarts or an endpoint is down. An
+//! endpoint that fails is retried with exponential backoff; its later
+//! notifications wait behind the first, so they arrive in order.
+//!
+//! A spool file names its endpoint by a hash of the URL, never the URL
+//! itself, and only configured endpoints are posted to: files for anything
+//! else are dropped. The spool directory must belong to us and be closed
+//! to everyone else.
+//!
+//! The payload is the service's `ServiceStatus` params from
+//! `interface.txt`, with `at` and `transitions` added.
+//!
+//! Nothing is printed here: what could not be delivered is handed back to
+//! the caller as a [`DeliveryError`].
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libc;
+use serde_json::{self, Value};
+
+use status::{status_params, Service};
+use tai::Tain;
+use watch::Event;
+
+mod http;
+pub use self::http::{post, ParseUrlError, Url};
+
+/// Where notifications are spooled unless told otherwise.
+pub const DEFAULT_SPOOL: &str = "/var/spool/rupervise";
+
+/// How long to wait before retrying an endpoint that failed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Backoff {
+        Backoff {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(300),
+        }
+    }
+}
+
+/// A change ready to be spooled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    /// When the change was seen, which orders the spool.
+    pub at: Tain,
+    pub body: Value,
+}
+
+impl Notification {
+    /// The notification of `event`, which happened to `service`: its
+    /// status params as they are now, with `at` and `transitions`.
+    pub fn new(event: &Event, service: &Service) -> Notification {
+        let mut body = status_params(service);
+        if let Some(map) = body.as_object_mut() {
+            map.insert("at".to_string(), Value::String(event.at.to_string()));
+            let transitions = event.transitions();
+            let transitions = transitions.iter().map(|t| Value::String(t.to_string())).collect();
+            map.insert("transitions".to_string(), Value::Array(transitions));
+        }
+        Notification { at: event.at, body }
+    }
+}
+
+/// A notification that was dropped, or could not be spooled or sent.
+#[derive(Debug)]
+pub enum DeliveryError {
+    /// The spool could not be read; it is tried again later.
+    ReadSpool(PathBuf, io::Error),
+    /// A notification could not be spooled, and is lost.
+    Spool(io::Error),
+    /// A spool file was for no configured endpoint, and was dropped.
+    UnknownEndpoint(PathBuf),
+    /// An endpoint turned the spool file `name` down for good, and it was
+    /// dropped.
+    Rejected { url: Url, name: String, code: u16 },
+}
+
+impl fmt::Display for DeliveryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DeliveryError::ReadSpool(ref spool, ref e) => {
+                write!(f, "unable to read {}: {}", spool.display(), e)
+            }
+            DeliveryError::Spool(ref e) => write!(f, "unable to spool notification: {}", e),
+            DeliveryError::UnknownEndpoint(ref path) => {
+                write!(f, "dropping {}: not for a configured endpoint", path.display())
+            }
+            DeliveryError::Rejected { ref url, ref name, code } => {
+                write!(f, "{} rejected {} with {}", url, name, code)
+            }
+        }
+    }
+}
+
+impl Error for DeliveryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            DeliveryError::ReadSpool(_, ref e) | DeliveryError::Spool(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Delivers notifications to a set of endpoints through a spool directory.
+#[derive(Debug)]
+pub struct Webhook {
+    endpoints: Vec<Url>,
+    spool: PathBuf,
+    pub timeout: Duration,
+    pub backoff: Backoff,
+    /// When each failing endpoint, by index, may be tried again, and the
+    /// delay that got it there.
+    retry_at: HashMap<usize, (Instant, Duration)>,
+    seq: u64,
+}
+
+impl Webhook {
+    /// Sets up delivery to `endpoints`, creating `spool` if need be.
+    /// Notifications already spooled there are sent by the next `deliver`.
+    ///
+    /// Fails if `spool` is not a directory owned by the effective user.
+    pub fn new<P: Into<PathBuf>>(endpoints: Vec<Url>, spool: P) -> io::Result<Webhook> {
+        let spool = spool.into();
+        open_spool(&spool)?;
+        Ok(Webhook {
+            endpoints,
+            spool,
+            timeout: Duration::from_secs(10),
+            backoff: Backoff::default(),
+            retry_at: HashMap::new(),
+            seq: 0,
+        })
+    }
+
+    /// Spools `notification` for every endpoint.
+    pub fn enqueue(&mut self, notification: &Notification) -> io::Result<()> {
+        let body = serde_json::to_string(&notification.body)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let at = notification.at.to_string();
+        for url in &self.endpoints {
+            self.seq += 1;
+            // TAI64N labels are fixed width, so names sort oldest first.
+            let name = format!("{}.{}.{:06}", &at[1..], process::id(), self.seq);
+            let tmp = self.spool.join(format!(".{}", name));
+            fs::write(&tmp, format!("{}\n{}", endpoint_key(url), body))?;
+            fs::rename(&tmp, self.spool.join(name))?;
+        }
+        Ok(())
+    }
+
+    /// Sends every spooled notification whose endpoint is not backing off.
+    ///
+    /// Returns how long until the next retry is due, or `None` if the spool
+    /// is empty, and the notifications that were dropped on the way.
+    pub fn deliver(&mut self) -> (Option<Duration>, Vec<DeliveryError>) {
+        let mut errors = Vec::new();
+        let mut names: Vec<String> = match fs::read_dir(&self.spool) {
+            Ok(entries) => entries.filter_map(|e| e.ok())
+                .filter_map(|e| e.file_name().into_string().ok())
+                .filter(|n| !n.starts_with('.'))
+                .collect(),
+            Err(e) => {
+                errors.push(DeliveryError::ReadSpool(self.spool.clone(), e));
+                return (Some(self.backoff.max), errors);
+            }
+        };
+        names.sort();
+
+        let mut next: Option<Duration> = None;
+        for name in names {
+            let path = self.spool.join(&name);
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(_) => continue,
+            };
+            let (key, body) = text.split_at(text.find('\n').unwrap_or(text.len()));
+            let body = body.trim_start_matches('\n');
+            let i = match self.endpoints.iter().position(|url| endpoint_key(url) == key) {
+                Some(i) => i,
+                None => {
+                    let _ = fs::remove_file(&path);
+                    errors.push(DeliveryError::UnknownEndpoint(path));
+                    continue;
+                }
+            };
+            let url = &self.endpoints[i];
+
+            let now = Instant::now();
+            if let Some(&(at, _)) = self.retry_at.get(&i) {
+                if at > now {
+                    next = Some(next.map_or(at - now, |n| n.min(at - now)));
+                    continue;
+                }
+            }
+
+            match post(url, body.as_bytes(), self.timeout) {
+                Ok(code) if (200..300).contains(&code) => {
+                    let _ = fs::remove_file(&path);
+                    self.retry_at.remove(&i);
+                }
+                // Sending it again will not help.
+                Ok(code) if (400..500).contains(&code) && code != 408 && code != 429 => {
+                    let _ = fs::remove_file(&path);
+                    self.retry_at.remove(&i);
+                    errors.push(DeliveryError::Rejected {
+                        url: url.clone(),
+                        name,
+                        code,
+                    });
+                }
+                _ => {
+                    let delay = match self.retry_at.get(&i) {
+                        Some(&(_, last)) => (last * 2).min(self.backoff.max),
+                        None => self.backoff.initial,
+                    };
+                    self.retry_at.insert(i, (now + delay, delay));
+                    next = Some(next.map_or(delay, |n| n.min(delay)));
+                }
+            }
+        }
+        (next, errors)
+    }
+
+    /// Delivers on a thread of its own, spooling each notification sent
+    /// down the returned channel. Anything left in the spool is sent first.
+    /// What could not be delivered is sent down `errors`.
+    pub fn spawn(mut self, errors: mpsc::Sender<DeliveryError>) -> mpsc::Sender<Notification> {
+        let (tx, rx) = mpsc::channel::<Notification>();
+        thread::spawn(move || {
+            let deliver = |hook: &mut Webhook| {
+                let (wait, failed) = hook.deliver();
+                for e in failed {
+                    let _ = errors.send(e);
+                }
+                wait
+            };
+            let mut wait = deliver(&mut self);
+            loop {
+                let received = match wait {
+                    Some(d) => match rx.recv_timeout(d) {
+                        Ok(notification) => Some(notification),
+                        Err(mpsc::RecvTimeoutError::Timeout) => None,
+                        Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                    },
+                    None => match rx.recv() {
+                        Ok(notification) => Some(notification),
+                        Err(_) => break,
+                    },
+                };
+                if let Some(notification) = received {
+                    if let Err(e) = self.enqueue(&notification) {
+                        let _ = errors.send(DeliveryError::Spool(e));
+                    }
+                }
+                wait = deliver(&mut self);
+            }
+        });
+        tx
+    }
+}
+
+/// Creates `spool` readable only by us, or checks that an existing one is
+/// ours and closes it to everyone else.
+fn open_spool(spool: &Path) -> io::Result<()> {
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(spool)?;
+    let meta = fs::symlink_metadata(spool)?;
+    if !meta.is_dir() || meta.uid() != unsafe { libc::geteuid() } {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied,
+                                  "spool is not a directory owned by this user"));
+    }
+    if meta.mode() & 0o077 != 0 {
+        fs::set_permissions(spool, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
+/// Names an endpoint in its spool files: the FNV-1a hash of its URL, so the
+/// same URL maps to the same key across restarts and builds.
+fn endpoint_key(url: &Url) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in url.to_string().bytes() {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+fn up_notification(path: &str) -> Notification {
+    use status::{ServiceStatus, SvstatType};
+    use testing;
+
+    let status = |pid| SvstatType::SvOk(ServiceStatus { duration: 3, ..testing::status(pid) });
+    let mut service = Service::new(path);
+    service.status = Some(status(Some(123)));
+    let event = Event {
+        index: 0,
+        service: PathBuf::from(path),
+        old: Some(status(None)),
+        new: status(Some(123)),
+        at: Tain::now(),
+    };
+    Notification::new(&event, &service)
+}
+
+#[test]
+fn payload_adds_the_change_to_the_status() {
+    let notification = up_notification("/service/web");
+    let map = notification.body.as_object().unwrap();
+    assert_eq!(Some(&Value::String("web".to_string())), map.get("service"));
+    assert_eq!(Some(&Value::U64(123)), map.get("pid"));
+    assert_eq!(Some(&Value::U64(3)), map.get("duration"));
+    assert!(map.contains_key("restarts"));
+    assert_eq!(Some(&Value::String(notification.at.to_string())), map.get("at"));
+    assert_eq!(Some(&Value::Array(vec![Value::String("up (pid 123)".to_string())])),
+               map.get("transitions"));
+}
+
+#[test]
+fn retries_until_accepted() {
+    use std::io::prelude::*;
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url: Url = format!("http://{}/hook", listener.local_addr().unwrap()).parse().unwrap();
+    let server = thread::spawn(move || {
+        let mut bodies = Vec::new();
+        for status in &["503 Service Unavailable", "200 OK"] {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            while !String::from_utf8_lossy(&request).contains("\"transitions\"") {
+                let n = conn.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            write!(conn, "HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status).unwrap();
+            bodies.push(String::from_utf8(request).unwrap());
+        }
+        bodies
+    });
+
+    let spool = ::std::env::temp_dir().join(format!("rupervise-spool-{}", process::id()));
+    let mut hook = Webhook::new(vec![url], &spool).unwrap();
+    assert_eq!(0o700, fs::metadata(&spool).unwrap().mode() & 0o777);
+    hook.backoff.initial = Duration::from_millis(0);
+    // Planted for somewhere we were never told to post to.
+    fs::write(spool.join("0"), "http://example.com/\n{}").unwrap();
+    hook.enqueue(&up_notification("/service/web")).unwrap();
+
+    let (wait, errors) = hook.deliver();
+    assert!(wait.is_some());
+    match errors[..] {
+        [DeliveryError::UnknownEndpoint(ref path)] => assert_eq!(spool.join("0"), *path),
+        ref other => panic!("unexpected errors {:?}", other),
+    }
+    assert_eq!(1, fs::read_dir(&spool).unwrap().count());
+    let (wait, errors) = hook.deliver();
+    assert_eq!(None, wait);
+    assert!(errors.is_empty());
+    assert_eq!(0, fs::read_dir(&spool).unwrap().count());
+
+    let bodies = server.join().unwrap();
+    assert!(bodies[1].starts_with("POST /hook HTTP/1.1\r\n"));
+    assert!(bodies[1].contains("\"isUp\":true"));
+    fs::remove_dir_all(&spool).unwrap();
+}